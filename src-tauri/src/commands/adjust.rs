@@ -0,0 +1,242 @@
+//! Tonal and colour adjustments on RGBA buffers.
+
+use std::sync::Mutex;
+
+use image::imageops::{self, FilterType};
+use tauri::State;
+
+use super::analysis::{luma, REC709};
+use super::{edit_source, finish_edit, Edited};
+use crate::documents::{DocumentId, Documents};
+use crate::error::AppError;
+use crate::logging::traced;
+
+/// Sigma above which `gaussian_blur` may take the downscaled fast path.
+const FAST_BLUR_MIN_SIGMA: f32 = 50.0;
+/// Image size, in pixels, above which the fast path kicks in.
+const FAST_BLUR_MIN_PIXELS: u64 = 4_000_000;
+/// Sigma the fast path blurs with once the image is downscaled.
+const FAST_BLUR_WORKING_SIGMA: f32 = 10.0;
+
+/// Applies brightness, contrast and saturation in one pass over the pixels.
+///
+/// - `brightness` is added to every channel, in [-1, 1] where 1 is full
+///   white.
+/// - `contrast` scales each channel's distance from mid-grey; 1 leaves it
+///   unchanged.
+/// - `saturation` scales each channel's distance from the pixel's Rec. 709
+///   luminance; 0 is greyscale and 1 is unchanged. Working around the
+///   luminance keeps perceived brightness constant as saturation changes.
+///
+/// Alpha is left untouched.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn adjust_image(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  brightness: f32,
+  contrast: f32,
+  saturation: f32,
+) -> Result<Edited, AppError> {
+  traced("adjust_image", async move {
+    if !(-1.0..=1.0).contains(&brightness) {
+      return Err(AppError::InvalidArgument(format!(
+        "brightness must be in [-1, 1], got {}",
+        brightness
+      )));
+    }
+    if !(contrast >= 0.0 && contrast.is_finite()) {
+      return Err(AppError::InvalidArgument(format!(
+        "contrast must be non-negative, got {}",
+        contrast
+      )));
+    }
+    if !(saturation >= 0.0 && saturation.is_finite()) {
+      return Err(AppError::InvalidArgument(format!(
+        "saturation must be non-negative, got {}",
+        saturation
+      )));
+    }
+
+    let mut image = edit_source(&documents, doc_id, data, width, height)?;
+    for pixel in image.pixels_mut() {
+      let mut rgb = [0.0f32; 3];
+      for (value, &channel) in rgb.iter_mut().zip(&pixel.0[..3]) {
+        let v = f32::from(channel) / 255.0 + brightness;
+        *value = (v - 0.5) * contrast + 0.5;
+      }
+
+      let luma = REC709[0] * rgb[0] + REC709[1] * rgb[1] + REC709[2] * rgb[2];
+      for (channel, value) in pixel.0[..3].iter_mut().zip(rgb) {
+        let v = luma + (value - luma) * saturation;
+        *channel = (v * 255.0).round().clamp(0.0, 255.0) as u8;
+      }
+    }
+
+    finish_edit(&documents, doc_id, image)
+  })
+  .await
+}
+
+/// Blurs an RGBA buffer with a Gaussian kernel of standard deviation
+/// `sigma` pixels.
+///
+/// With `fast` set, large blurs (sigma over 50) on large images (over 4
+/// megapixels) are done on a downscaled copy that is scaled back up
+/// afterwards. That is far quicker and fine for a live preview, but is only
+/// an approximation of the exact blur, so leave `fast` off for final
+/// output.
+#[tauri::command]
+pub async fn gaussian_blur(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  sigma: f32,
+  fast: bool,
+) -> Result<Edited, AppError> {
+  traced("gaussian_blur", async move {
+    if !(sigma > 0.0 && sigma.is_finite()) {
+      return Err(AppError::InvalidArgument(format!(
+        "blur sigma must be positive, got {}",
+        sigma
+      )));
+    }
+
+    let image = edit_source(&documents, doc_id, data, width, height)?;
+    let (width, height) = image.dimensions();
+    let pixels = u64::from(width) * u64::from(height);
+
+    let blurred = if fast && sigma > FAST_BLUR_MIN_SIGMA && pixels > FAST_BLUR_MIN_PIXELS {
+      let factor = sigma / FAST_BLUR_WORKING_SIGMA;
+      let small_w = ((width as f32 / factor).round() as u32).max(1);
+      let small_h = ((height as f32 / factor).round() as u32).max(1);
+      let small = imageops::resize(&image, small_w, small_h, FilterType::Triangle);
+      let small = imageops::blur(&small, FAST_BLUR_WORKING_SIGMA);
+      imageops::resize(&small, width, height, FilterType::Triangle)
+    } else {
+      imageops::blur(&image, sigma)
+    };
+
+    finish_edit(&documents, doc_id, blurred)
+  })
+  .await
+}
+
+/// Sharpens an RGBA buffer by unsharp masking.
+///
+/// The image is blurred with a Gaussian of sigma `radius`, and `amount`
+/// times the difference between original and blur is added back. Channels
+/// whose difference is no more than `threshold` are left alone, so flat
+/// areas like sky don't have their noise amplified. Alpha is left
+/// untouched.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn unsharp_mask(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  amount: f32,
+  radius: f32,
+  threshold: u8,
+) -> Result<Edited, AppError> {
+  traced("unsharp_mask", async move {
+    if !(amount >= 0.0 && amount.is_finite()) {
+      return Err(AppError::InvalidArgument(format!(
+        "sharpen amount must be non-negative, got {}",
+        amount
+      )));
+    }
+    if !(radius > 0.0 && radius.is_finite()) {
+      return Err(AppError::InvalidArgument(format!(
+        "sharpen radius must be positive, got {}",
+        radius
+      )));
+    }
+
+    let mut image = edit_source(&documents, doc_id, data, width, height)?;
+    let blurred = imageops::blur(&image, radius);
+
+    for (pixel, blurred) in image.pixels_mut().zip(blurred.pixels()) {
+      for (channel, &soft) in pixel.0[..3].iter_mut().zip(&blurred.0[..3]) {
+        let diff = i16::from(*channel) - i16::from(soft);
+        if diff.unsigned_abs() > u16::from(threshold) {
+          let sharpened = f32::from(*channel) + amount * f32::from(diff);
+          *channel = sharpened.round().clamp(0.0, 255.0) as u8;
+        }
+      }
+    }
+
+    finish_edit(&documents, doc_id, image)
+  })
+  .await
+}
+
+/// Converts an RGBA buffer to greyscale, keeping it RGBA with equal colour
+/// channels.
+///
+/// `method` picks how each pixel's grey is derived:
+/// - "luminance": Rec. 709 weighted sum, closest to perceived brightness.
+/// - "average": plain mean of R, G and B.
+/// - "lightness": HSL lightness, the midpoint of the largest and smallest
+///   channel.
+/// - "single-channel": one channel as-is, chosen by `channel` ("red",
+///   "green" or "blue"; defaults to green). Like shooting B&W through a
+///   coloured filter.
+///
+/// Alpha is left untouched.
+#[tauri::command]
+pub async fn to_grayscale(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  method: String,
+  channel: Option<String>,
+) -> Result<Edited, AppError> {
+  traced("to_grayscale", async move {
+    let grey: fn(u8, u8, u8) -> u8 = match method.as_str() {
+      "luminance" => luma,
+      "average" => |r, g, b| ((u16::from(r) + u16::from(g) + u16::from(b) + 1) / 3) as u8,
+      "lightness" => |r, g, b| {
+        let max = u16::from(r.max(g).max(b));
+        let min = u16::from(r.min(g).min(b));
+        (max + min).div_ceil(2) as u8
+      },
+      "single-channel" => match channel.as_deref().unwrap_or("green") {
+        "red" => |r, _, _| r,
+        "green" => |_, g, _| g,
+        "blue" => |_, _, b| b,
+        other => {
+          return Err(AppError::InvalidArgument(format!(
+            "unknown colour channel: {}",
+            other
+          )))
+        }
+      },
+      other => {
+        return Err(AppError::InvalidArgument(format!(
+          "unknown greyscale method: {}",
+          other
+        )))
+      }
+    };
+
+    let mut image = edit_source(&documents, doc_id, data, width, height)?;
+    for pixel in image.pixels_mut() {
+      let [r, g, b, _] = pixel.0;
+      let value = grey(r, g, b);
+      pixel.0[..3].fill(value);
+    }
+
+    finish_edit(&documents, doc_id, image)
+  })
+  .await
+}
@@ -0,0 +1,201 @@
+//! Statistics computed from pixel data.
+
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+use serde::Serialize;
+
+use super::file::decode_file;
+use super::{rgba_from_raw, ImageData};
+use crate::error::AppError;
+use crate::logging::traced;
+
+/// Rec. 709 luma weights for linear combination of R, G and B.
+pub(crate) const REC709: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Side of the square windows SSIM is averaged over.
+const SSIM_WINDOW: u32 = 8;
+/// SSIM stabilising constants, (0.01 * 255)^2 and (0.03 * 255)^2.
+const SSIM_C1: f64 = 6.5025;
+const SSIM_C2: f64 = 58.5225;
+
+/// Per-channel histograms, each with 256 bins indexed by value.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramData {
+  pub red: Vec<u32>,
+  pub green: Vec<u32>,
+  pub blue: Vec<u32>,
+  /// Rec. 709 luminance.
+  pub luminance: Vec<u32>,
+}
+
+/// How two images differ, from `compare_images`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffResult {
+  pub width: u32,
+  pub height: u32,
+  /// Pixels where some channel differs by more than the threshold.
+  pub pixels_different: u64,
+  /// Largest difference in any channel of any pixel, 0-255.
+  pub max_delta: u8,
+  /// Structural similarity of the two luminance channels, 1 for identical.
+  pub ssim: f64,
+  /// Image A faded towards white, with changed pixels painted from yellow
+  /// (just over the threshold) to red (maximum difference).
+  pub heatmap: Option<ImageData>,
+}
+
+/// Compares the images at `path_a` and `path_b` pixel by pixel.
+///
+/// A pixel counts as different when any of its channels, alpha included,
+/// differs by more than `threshold` (default 0), so a small threshold keeps
+/// anti-aliasing jitter from being flagged. `max_delta` and `ssim` ignore
+/// the threshold. The heatmap is only built when `heatmap` is set. Images
+/// of different sizes are an error rather than being compared.
+#[tauri::command]
+pub async fn compare_images(
+  path_a: String,
+  path_b: String,
+  threshold: Option<u8>,
+  heatmap: Option<bool>,
+) -> Result<DiffResult, AppError> {
+  traced("compare_images", async move {
+    let a = decode_file(Path::new(&path_a))?.into_rgba8();
+    let b = decode_file(Path::new(&path_b))?.into_rgba8();
+    if a.dimensions() != b.dimensions() {
+      return Err(AppError::InvalidArgument(format!(
+        "image sizes differ: {}x{} and {}x{}",
+        a.width(),
+        a.height(),
+        b.width(),
+        b.height()
+      )));
+    }
+
+    let threshold = threshold.unwrap_or(0);
+    let mut map = heatmap
+      .unwrap_or(false)
+      .then(|| RgbaImage::new(a.width(), a.height()));
+    let mut pixels_different = 0u64;
+    let mut max_delta = 0u8;
+
+    for ((x, y, pa), pb) in a.enumerate_pixels().zip(b.pixels()) {
+      let delta = pa
+        .0
+        .iter()
+        .zip(&pb.0)
+        .map(|(&ca, &cb)| ca.abs_diff(cb))
+        .max()
+        .unwrap_or(0);
+      max_delta = max_delta.max(delta);
+      let changed = delta > threshold;
+      if changed {
+        pixels_different += 1;
+      }
+
+      if let Some(map) = map.as_mut() {
+        let pixel = if changed {
+          Rgba([255, 255 - delta, 0, 255])
+        } else {
+          let faded = 255 - (255 - luma(pa[0], pa[1], pa[2])) / 4;
+          Rgba([faded, faded, faded, 255])
+        };
+        map.put_pixel(x, y, pixel);
+      }
+    }
+
+    Ok(DiffResult {
+      width: a.width(),
+      height: a.height(),
+      pixels_different,
+      max_delta,
+      ssim: ssim(&a, &b),
+      heatmap: map.as_ref().map(ImageData::from_rgba),
+    })
+  })
+  .await
+}
+
+/// Mean SSIM of the luminance of two same-sized images, over
+/// non-overlapping windows. Windows at the right and bottom edges may be
+/// smaller than `SSIM_WINDOW`.
+fn ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+  let (width, height) = a.dimensions();
+  if width == 0 || height == 0 {
+    return 1.0;
+  }
+  let luma_at = |image: &RgbaImage, x, y| {
+    let [r, g, b, _] = image.get_pixel(x, y).0;
+    f64::from(luma(r, g, b))
+  };
+
+  let mut total = 0.0;
+  let mut windows = 0u32;
+  for top in (0..height).step_by(SSIM_WINDOW as usize) {
+    for left in (0..width).step_by(SSIM_WINDOW as usize) {
+      let (mut sum_a, mut sum_b) = (0.0, 0.0);
+      let (mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0);
+      let mut n = 0.0;
+      for y in top..(top + SSIM_WINDOW).min(height) {
+        for x in left..(left + SSIM_WINDOW).min(width) {
+          let (va, vb) = (luma_at(a, x, y), luma_at(b, x, y));
+          sum_a += va;
+          sum_b += vb;
+          sum_aa += va * va;
+          sum_bb += vb * vb;
+          sum_ab += va * vb;
+          n += 1.0;
+        }
+      }
+
+      let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+      let var_a = sum_aa / n - mean_a * mean_a;
+      let var_b = sum_bb / n - mean_b * mean_b;
+      let covar = sum_ab / n - mean_a * mean_b;
+      total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covar + SSIM_C2))
+        / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+      windows += 1;
+    }
+  }
+  total / f64::from(windows)
+}
+
+/// Counts R, G, B and luminance values across an RGBA buffer in a single
+/// pass. Alpha is ignored.
+#[tauri::command]
+pub async fn compute_histogram(
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+) -> Result<HistogramData, AppError> {
+  traced("compute_histogram", async move {
+    let image = rgba_from_raw(data, width, height)?;
+
+    let mut red = vec![0u32; 256];
+    let mut green = vec![0u32; 256];
+    let mut blue = vec![0u32; 256];
+    let mut luminance = vec![0u32; 256];
+
+    for pixel in image.as_raw().chunks_exact(4) {
+      let [r, g, b] = [pixel[0], pixel[1], pixel[2]];
+      red[r as usize] += 1;
+      green[g as usize] += 1;
+      blue[b as usize] += 1;
+      luminance[luma(r, g, b) as usize] += 1;
+    }
+
+    Ok(HistogramData {
+      red,
+      green,
+      blue,
+      luminance,
+    })
+  })
+  .await
+}
+
+/// Rec. 709 luma of an 8-bit colour, rounded to the nearest value.
+pub(crate) fn luma(r: u8, g: u8, b: u8) -> u8 {
+  let y = REC709[0] * f32::from(r) + REC709[1] * f32::from(g) + REC709[2] * f32::from(b);
+  y.round().min(255.0) as u8
+}
@@ -0,0 +1,347 @@
+//! Processing whole directories of images.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use serde::Serialize;
+use tauri::{Manager, State, Window};
+
+use super::file::{decode_file, encode_file, is_supported_image, output_extension, EncodeOptions};
+use crate::error::AppError;
+use crate::logging::{traced, traced_sync};
+use crate::sync;
+use crate::tray;
+
+/// Shared flag that asks a running batch to stop, held in managed state.
+#[derive(Debug, Default, Clone)]
+pub struct BatchCancel {
+  cancelled: Arc<AtomicBool>,
+  running: Arc<AtomicBool>,
+}
+
+impl BatchCancel {
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::SeqCst);
+  }
+
+  /// Cancels any running batch and blocks until the files it is currently
+  /// writing are finished, or `timeout` passes. Returns whether the batch
+  /// stopped in time.
+  pub fn cancel_and_wait(&self, timeout: Duration) -> bool {
+    self.cancel();
+    let deadline = Instant::now() + timeout;
+    while self.running.load(Ordering::SeqCst) {
+      if Instant::now() >= deadline {
+        return false;
+      }
+      thread::sleep(Duration::from_millis(20));
+    }
+    true
+  }
+
+  fn reset(&self) {
+    self.cancelled.store(false, Ordering::SeqCst);
+  }
+
+  fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::SeqCst)
+  }
+}
+
+/// Marks a batch as running until dropped.
+struct RunningGuard<'a>(&'a AtomicBool);
+
+impl<'a> RunningGuard<'a> {
+  fn new(running: &'a AtomicBool) -> Self {
+    running.store(true, Ordering::SeqCst);
+    Self(running)
+  }
+}
+
+impl Drop for RunningGuard<'_> {
+  fn drop(&mut self) {
+    self.0.store(false, Ordering::SeqCst);
+  }
+}
+
+/// Outcome of a batch run.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+  /// Number of files written.
+  pub processed: u32,
+  pub cancelled: bool,
+}
+
+/// Payload of the `batch-progress` event, emitted after each file.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchProgress {
+  pub processed: usize,
+  pub total: usize,
+  pub current_file: String,
+}
+
+/// Converts every image in `input_dir` to `target_format`, writing the
+/// results to `output_dir` under the same file stem (see `output_paths`
+/// for sources that share one). `output_dir` must be a different directory.
+///
+/// Files are converted in parallel on up to `max_threads` threads (all cores
+/// if unset). Files that fail to convert are skipped so one bad file doesn't
+/// abort the batch. The run stops early if `cancel_batch` is called.
+#[tauri::command]
+pub async fn batch_convert(
+  window: Window,
+  cancel: State<'_, BatchCancel>,
+  input_dir: String,
+  output_dir: String,
+  target_format: String,
+  max_threads: Option<usize>,
+) -> Result<BatchResult, AppError> {
+  traced("batch_convert", async move {
+    check_output_dir(&input_dir, &output_dir)?;
+    let extension = output_extension(&target_format).ok_or_else(|| {
+      AppError::UnsupportedFormat(format!("unsupported output format: {}", target_format))
+    })?;
+    let images = batch_inputs(&input_dir)?;
+    let jobs = output_paths(Path::new(&output_dir), images, |_| extension);
+
+    run_batch(&window, &cancel, &jobs, max_threads, |source, output| {
+      let image = decode_file(source)?;
+      encode_file(output, &image, &target_format, &EncodeOptions::default())
+    })
+  })
+  .await
+}
+
+/// Refuses an `output_dir` that is `input_dir` under another name, where
+/// outputs in the source's format would overwrite the originals.
+pub(crate) fn check_output_dir(input_dir: &str, output_dir: &str) -> Result<(), AppError> {
+  let same_dir = fs::canonicalize(input_dir)
+    .ok()
+    .zip(fs::canonicalize(output_dir).ok())
+    .is_some_and(|(input, output)| input == output);
+  if same_dir {
+    return Err(AppError::InvalidArgument(format!(
+      "the output directory must differ from the input directory {}",
+      input_dir
+    )));
+  }
+  Ok(())
+}
+
+/// The supported images in `input_dir`, or an error listing what was
+/// skipped if there are none.
+pub(crate) fn batch_inputs(input_dir: &str) -> Result<Vec<PathBuf>, AppError> {
+  let (images, skipped) = collect_images(Path::new(input_dir))?;
+  if images.is_empty() {
+    return Err(AppError::InvalidArgument(format!(
+      "no supported images found in {}; skipped: {}",
+      input_dir,
+      skipped.join(", ")
+    )));
+  }
+  Ok(images)
+}
+
+/// Pairs each of `files` with where its output goes in `output_dir`: the
+/// same stem, with the extension `extension` picks for it.
+///
+/// Sources that would land on the same name, such as `a.png` and `a.jpg`
+/// converted to WebP, keep their whole file name instead (`a.png.webp` and
+/// `a.jpg.webp`), with a `-1`, `-2`, ... suffix if even that is taken.
+/// Names are compared ignoring case, as on Windows and macOS.
+pub(crate) fn output_paths<'a>(
+  output_dir: &Path,
+  files: Vec<PathBuf>,
+  extension: impl Fn(&Path) -> &'a str,
+) -> Vec<(PathBuf, PathBuf)> {
+  let stem = |source: &Path| {
+    source
+      .file_stem()
+      .unwrap_or_default()
+      .to_string_lossy()
+      .into_owned()
+  };
+
+  let mut uses = HashMap::<String, usize>::new();
+  for source in &files {
+    let name = format!("{}.{}", stem(source), extension(source));
+    *uses.entry(name.to_lowercase()).or_default() += 1;
+  }
+
+  let mut taken = HashSet::new();
+  files
+    .into_iter()
+    .map(|source| {
+      let extension = extension(&source);
+      let mut base = stem(&source);
+      if uses[&format!("{}.{}", base, extension).to_lowercase()] > 1 {
+        base = source
+          .file_name()
+          .unwrap_or_default()
+          .to_string_lossy()
+          .into_owned();
+      }
+      let mut name = format!("{}.{}", base, extension);
+      let mut suffix = 1;
+      while !taken.insert(name.to_lowercase()) {
+        name = format!("{}-{}.{}", base, suffix, extension);
+        suffix += 1;
+      }
+      (source, output_dir.join(name))
+    })
+    .collect()
+}
+
+/// Runs `job` over each source and output pair in `jobs` in parallel on up
+/// to `max_threads` threads (all cores if unset), emitting `batch-progress`
+/// after each file.
+///
+/// A failing file is counted and skipped rather than aborting the batch,
+/// and `cancel_batch` stops the run before the next file starts. Fails up
+/// front if two jobs share an output; `output_paths` never pairs them so.
+pub(crate) fn run_batch<F>(
+  window: &Window,
+  cancel: &BatchCancel,
+  jobs: &[(PathBuf, PathBuf)],
+  max_threads: Option<usize>,
+  job: F,
+) -> Result<BatchResult, AppError>
+where
+  F: Fn(&Path, &Path) -> Result<(), AppError> + Sync,
+{
+  // Jobs run in parallel, so two writing the same file would interleave.
+  let mut outputs = HashSet::new();
+  if let Some((_, output)) = jobs.iter().find(|(_, output)| !outputs.insert(output)) {
+    return Err(AppError::InvalidArgument(format!(
+      "more than one file would be written to {}",
+      output.display()
+    )));
+  }
+
+  // A cancel aimed at a previous run shouldn't stop this one.
+  cancel.reset();
+
+  let pool = ThreadPoolBuilder::new()
+    .num_threads(max_threads.unwrap_or(0))
+    .build()
+    .map_err(|err| AppError::Io(format!("failed to start batch workers: {}", err)))?;
+
+  let _running = RunningGuard::new(&cancel.running);
+  let app = window.app_handle();
+  let total = jobs.len();
+  let processed = AtomicUsize::new(0);
+  let succeeded = AtomicU32::new(0);
+  // Held while bumping `processed` and emitting, so events arrive in order.
+  let progress = Mutex::new(());
+
+  pool.install(|| {
+    jobs.par_iter().for_each(|(source, output)| {
+      if cancel.is_cancelled() {
+        return;
+      }
+
+      if job(source, output).is_ok() {
+        succeeded.fetch_add(1, Ordering::SeqCst);
+      }
+
+      let _guard = sync::lock(&progress);
+      let done = processed.fetch_add(1, Ordering::SeqCst) + 1;
+      tray::set_progress(&app, Some((done, total)));
+      let _ = window.emit(
+        "batch-progress",
+        BatchProgress {
+          processed: done,
+          total,
+          current_file: source.display().to_string(),
+        },
+      );
+    });
+  });
+  tray::set_progress(&app, None);
+
+  Ok(BatchResult {
+    processed: succeeded.into_inner(),
+    cancelled: processed.into_inner() < total,
+  })
+}
+
+/// Stops the running batch after the file it is currently converting.
+#[tauri::command]
+pub fn cancel_batch(cancel: State<'_, BatchCancel>) -> Result<(), AppError> {
+  traced_sync("cancel_batch", || {
+    cancel.cancel();
+  })
+}
+
+/// Lists the supported images directly inside `dir`, sorted by path,
+/// along with the names of any other files that were passed over.
+fn collect_images(dir: &Path) -> Result<(Vec<PathBuf>, Vec<String>), AppError> {
+  let entries = fs::read_dir(dir).map_err(|err| AppError::read(dir, &err))?;
+
+  let mut images = Vec::new();
+  let mut skipped = Vec::new();
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if !path.is_file() {
+      continue;
+    }
+    if is_supported_image(&path) {
+      images.push(path);
+    } else {
+      skipped.push(entry.file_name().to_string_lossy().into_owned());
+    }
+  }
+  images.sort();
+
+  Ok((images, skipped))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn output_paths_keeps_sources_with_the_same_stem_apart() {
+    let files = vec![
+      PathBuf::from("in/a.jpg"),
+      PathBuf::from("in/A.png"),
+      PathBuf::from("in/a.png.gif"),
+      PathBuf::from("in/b.png"),
+    ];
+    let outputs: Vec<_> = output_paths(Path::new("out"), files, |_| "webp")
+      .into_iter()
+      .map(|(_, output)| output)
+      .collect();
+    assert_eq!(
+      outputs,
+      [
+        Path::new("out/a.jpg.webp"),
+        Path::new("out/A.png.webp"),
+        Path::new("out/a.png-1.webp"),
+        Path::new("out/b.webp"),
+      ]
+    );
+  }
+
+  #[test]
+  fn check_output_dir_refuses_the_input_dir() {
+    let input = std::env::temp_dir().join(format!("image-pro-{}-batch-in", std::process::id()));
+    let output = input.with_file_name(format!("image-pro-{}-batch-out", std::process::id()));
+    fs::create_dir_all(&input).unwrap();
+    let input = input.to_string_lossy();
+
+    let same = check_output_dir(&input, &format!("{}/.", input));
+    let other = check_output_dir(&input, &output.to_string_lossy());
+    let _ = fs::remove_dir(&*input);
+
+    assert!(matches!(same, Err(AppError::InvalidArgument(_))));
+    assert!(other.is_ok());
+  }
+}
@@ -0,0 +1,71 @@
+//! Blank images for new documents.
+
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+
+use super::file::{check_pixels, max_pixels};
+use super::ImageData;
+use crate::error::AppError;
+use crate::logging::traced;
+
+/// How to fill a new canvas. From JS this is `{ Solid: [r, g, b, a] }` or
+/// `{ LinearGradient: { start, end, angle } }`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum Fill {
+  Solid([u8; 4]),
+  /// Runs from `start` to `end` across the whole canvas. `angle` is in
+  /// degrees clockwise, with 0 running left to right and 90 top to bottom.
+  LinearGradient {
+    start: [u8; 4],
+    end: [u8; 4],
+    angle: f32,
+  },
+}
+
+/// Creates a `width` by `height` image filled with `fill`.
+///
+/// The canvas has to fit the pixel budget set by `set_max_pixels`.
+#[tauri::command]
+pub async fn new_canvas(width: u32, height: u32, fill: Fill) -> Result<ImageData, AppError> {
+  traced("new_canvas", async move {
+    if width == 0 || height == 0 {
+      return Err(AppError::InvalidArgument(format!(
+        "a canvas can't be {}x{}",
+        width, height
+      )));
+    }
+    check_pixels("the canvas", width, height, max_pixels())?;
+
+    let image = match fill {
+      Fill::Solid(color) => RgbaImage::from_pixel(width, height, Rgba(color)),
+      Fill::LinearGradient { start, end, angle } => {
+        if !angle.is_finite() {
+          return Err(AppError::InvalidArgument(format!(
+            "gradient angle must be finite, got {}",
+            angle
+          )));
+        }
+        linear_gradient(width, height, start, end, angle)
+      }
+    };
+    Ok(ImageData::from_rgba(&image))
+  })
+  .await
+}
+
+/// A gradient whose ends touch the two corners furthest along `angle`.
+fn linear_gradient(width: u32, height: u32, start: [u8; 4], end: [u8; 4], angle: f32) -> RgbaImage {
+  let (sin, cos) = angle.to_radians().sin_cos();
+  let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+  let half_length = (cx * cos).abs() + (cy * sin).abs();
+
+  RgbaImage::from_fn(width, height, |x, y| {
+    let along = (x as f32 + 0.5 - cx) * cos + (y as f32 + 0.5 - cy) * sin;
+    let t = (0.5 + along / (2.0 * half_length)).clamp(0.0, 1.0);
+    let mut pixel = [0; 4];
+    for ((out, &from), &to) in pixel.iter_mut().zip(&start).zip(&end) {
+      *out = (f32::from(from) + (f32::from(to) - f32::from(from)) * t).round() as u8;
+    }
+    Rgba(pixel)
+  })
+}
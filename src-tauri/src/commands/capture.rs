@@ -0,0 +1,80 @@
+//! Screenshots of monitors, whole or in part.
+
+use image::RgbaImage;
+use tauri::{AppHandle, State};
+use xcap::Monitor;
+
+use super::transform::{crop, CropRect};
+use super::ImageData;
+use crate::error::AppError;
+use crate::logging::traced;
+use crate::screenshot::{self, CaptureShortcut};
+
+/// Captures monitor `monitor`, counted from 0 in the order the OS lists
+/// them, or the primary monitor for `None`.
+///
+/// `region`, in the monitor's physical pixels, keeps just that part of it.
+/// Like `crop_image`, the part past the monitor's edges is clamped away.
+///
+/// On macOS the app needs the Screen Recording permission; without it the
+/// capture shows only the desktop background.
+#[tauri::command]
+pub async fn capture_screen(
+  monitor: Option<usize>,
+  region: Option<CropRect>,
+) -> Result<ImageData, AppError> {
+  traced("capture_screen", async move {
+    let captured = capture_monitor(monitor)?;
+    let image = match region {
+      Some(region) => crop(&captured, region.x, region.y, region.width, region.height)?,
+      None => captured,
+    };
+    Ok(ImageData::from_rgba(&image))
+  })
+  .await
+}
+
+/// Changes the global shortcut that captures the primary monitor into the
+/// editor, such as "CmdOrCtrl+Shift+4" (the default) or "Alt+PrintScreen".
+///
+/// If the new shortcut can't be registered, for example because another
+/// app holds it, the old one stays active.
+#[tauri::command]
+pub async fn set_capture_shortcut(
+  app: AppHandle,
+  shortcut: State<'_, CaptureShortcut>,
+  accelerator: String,
+) -> Result<(), AppError> {
+  traced("set_capture_shortcut", async move {
+    screenshot::register(&app, &shortcut, &accelerator)
+  })
+  .await
+}
+
+pub(crate) fn capture_monitor(monitor: Option<usize>) -> Result<RgbaImage, AppError> {
+  let capture_error =
+    |err: xcap::XCapError| AppError::Io(format!("failed to capture the screen: {}", err));
+  let mut monitors = Monitor::all().map_err(capture_error)?;
+
+  let monitor = match monitor {
+    Some(index) if index < monitors.len() => monitors.swap_remove(index),
+    Some(index) => {
+      return Err(AppError::InvalidArgument(format!(
+        "monitor {} is out of range: {} monitor(s) connected",
+        index,
+        monitors.len()
+      )))
+    }
+    None if monitors.is_empty() => {
+      return Err(AppError::NotFound("no monitor is connected".into()))
+    }
+    None => {
+      let primary = monitors
+        .iter()
+        .position(|monitor| monitor.is_primary().unwrap_or(false))
+        .unwrap_or(0);
+      monitors.swap_remove(primary)
+    }
+  };
+  monitor.capture_image().map_err(capture_error)
+}
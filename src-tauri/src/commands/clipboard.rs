@@ -0,0 +1,69 @@
+//! Exchanging images with the system clipboard.
+
+use std::borrow::Cow;
+
+use arboard::Clipboard;
+
+use super::{rgba_from_raw, ImageData};
+use crate::error::AppError;
+use crate::logging::traced;
+
+/// Reads the image on the system clipboard as RGBA.
+///
+/// Fails with `NotFound` when the clipboard holds only text or nothing, so
+/// the UI can treat that case as a notice rather than an error.
+#[tauri::command]
+pub async fn paste_image_from_clipboard() -> Result<ImageData, AppError> {
+  traced("paste_image_from_clipboard", async move {
+    let mut clipboard = open_clipboard()?;
+    let image = clipboard.get_image().map_err(|err| match err {
+      arboard::Error::ContentNotAvailable => AppError::NotFound("no image on clipboard".into()),
+      err => AppError::Io(format!("failed to read the clipboard: {}", err)),
+    })?;
+
+    let too_large = |_| AppError::DecodeFailed("clipboard image is too large".into());
+    let width = u32::try_from(image.width).map_err(too_large)?;
+    let height = u32::try_from(image.height).map_err(too_large)?;
+    let image = rgba_from_raw(image.bytes.into_owned(), width, height)?;
+    Ok(ImageData::from_rgba(&image))
+  })
+  .await
+}
+
+/// Puts an RGBA buffer on the system clipboard.
+///
+/// Alpha is kept: on Windows the image goes up both as PNG and as a DIB with
+/// an alpha channel. Apps that read the DIB but ignore its alpha would show
+/// whatever colour sits under transparent pixels, usually black, so fully
+/// transparent pixels are written as transparent white to make those apps
+/// show a white background instead. Partially transparent pixels are left
+/// as they are and may look darker in such apps.
+#[tauri::command]
+pub async fn copy_image_to_clipboard(
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+) -> Result<(), AppError> {
+  traced("copy_image_to_clipboard", async move {
+    let mut image = rgba_from_raw(data, width, height)?;
+    for pixel in image.pixels_mut() {
+      if pixel[3] == 0 {
+        pixel.0 = [255, 255, 255, 0];
+      }
+    }
+
+    let mut clipboard = open_clipboard()?;
+    clipboard
+      .set_image(arboard::ImageData {
+        width: width as usize,
+        height: height as usize,
+        bytes: Cow::Owned(image.into_raw()),
+      })
+      .map_err(|err| AppError::Io(format!("failed to write the clipboard: {}", err)))
+  })
+  .await
+}
+
+fn open_clipboard() -> Result<Clipboard, AppError> {
+  Clipboard::new().map_err(|err| AppError::Io(format!("failed to open the clipboard: {}", err)))
+}
@@ -0,0 +1,59 @@
+//! Colour management with ICC profiles.
+
+use std::sync::Mutex;
+
+use lcms2::{ColorSpaceSignature, Flags, Intent, PixelFormat, Profile, Transform};
+use tauri::State;
+
+use super::{edit_source, finish_edit, Edited};
+use crate::documents::{DocumentId, Documents};
+use crate::error::AppError;
+use crate::logging::traced;
+
+/// Converts an RGBA buffer from the colour space described by the ICC
+/// profile `src_profile` into sRGB, as browsers and most displays expect.
+///
+/// This is what stops Adobe RGB and Display P3 photos looking washed out.
+/// An empty profile means the image is already sRGB, and it is returned
+/// untouched. Only RGB profiles are accepted. Alpha is copied through.
+#[tauri::command]
+pub async fn convert_to_srgb(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  src_profile: Vec<u8>,
+) -> Result<Edited, AppError> {
+  traced("convert_to_srgb", async move {
+    let mut image = edit_source(&documents, doc_id, data, width, height)?;
+    if src_profile.is_empty() {
+      return finish_edit(&documents, doc_id, image);
+    }
+
+    let source = Profile::new_icc(&src_profile)
+      .map_err(|err| AppError::InvalidArgument(format!("invalid ICC profile: {}", err)))?;
+    if source.color_space() != ColorSpaceSignature::RgbData {
+      return Err(AppError::InvalidArgument(format!(
+        "cannot convert an RGB image with a {:?} profile",
+        source.color_space()
+      )));
+    }
+
+    let transform = Transform::<u8, u8>::new_flags(
+      &source,
+      PixelFormat::RGBA_8,
+      &Profile::new_srgb(),
+      PixelFormat::RGBA_8,
+      Intent::Perceptual,
+      Flags::COPY_ALPHA,
+    )
+    .map_err(|err| {
+      AppError::InvalidArgument(format!("failed to build colour transform: {}", err))
+    })?;
+    transform.transform_in_place(&mut image);
+
+    finish_edit(&documents, doc_id, image)
+  })
+  .await
+}
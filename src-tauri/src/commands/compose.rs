@@ -0,0 +1,256 @@
+//! Compositing one image onto another.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use image::{imageops, DynamicImage, RgbaImage};
+use tauri::{State, Window};
+
+use super::batch::{
+  batch_inputs, check_output_dir, output_paths, run_batch, BatchCancel, BatchResult,
+};
+use super::file::{decode_file, encode_file, output_extension, EncodeOptions};
+use super::{edit_source, finish_edit, rgba_from_raw, Edited};
+use crate::documents::{DocumentId, Documents};
+use crate::error::AppError;
+use crate::logging::traced;
+
+/// Where a watermark sits on the base image.
+#[derive(Debug, Clone, Copy)]
+enum Anchor {
+  Start,
+  Center,
+  End,
+}
+
+/// Maps a position name from the UI to horizontal and vertical anchors.
+fn parse_position(name: &str) -> Result<(Anchor, Anchor), AppError> {
+  use Anchor::*;
+  Ok(match name.to_ascii_lowercase().as_str() {
+    "top-left" => (Start, Start),
+    "top" | "top-center" => (Center, Start),
+    "top-right" => (End, Start),
+    "left" | "center-left" => (Start, Center),
+    "center" => (Center, Center),
+    "right" | "center-right" => (End, Center),
+    "bottom-left" => (Start, End),
+    "bottom" | "bottom-center" => (Center, End),
+    "bottom-right" => (End, End),
+    other => {
+      return Err(AppError::InvalidArgument(format!(
+        "unknown watermark position: {}",
+        other
+      )))
+    }
+  })
+}
+
+/// Offset of a `mark`-long span anchored inside a `base`-long one. Margins
+/// only apply against an edge, never to a centred axis.
+fn offset(anchor: Anchor, base: u32, mark: u32, margin: u32) -> i64 {
+  let (base, mark, margin) = (i64::from(base), i64::from(mark), i64::from(margin));
+  match anchor {
+    Anchor::Start => margin,
+    Anchor::Center => (base - mark) / 2,
+    Anchor::End => base - mark - margin,
+  }
+}
+
+/// A watermark ready to stamp: alpha already scaled by the opacity, so the
+/// same mark can be applied to many images.
+struct Watermark {
+  image: RgbaImage,
+  anchors: (Anchor, Anchor),
+  margin: u32,
+}
+
+impl Watermark {
+  fn new(
+    mut image: RgbaImage,
+    position: &str,
+    opacity: f32,
+    margin: u32,
+  ) -> Result<Self, AppError> {
+    if !(0.0..=1.0).contains(&opacity) {
+      return Err(AppError::InvalidArgument(format!(
+        "opacity must be between 0 and 1, got {}",
+        opacity
+      )));
+    }
+    let anchors = parse_position(position)?;
+
+    for pixel in image.pixels_mut() {
+      pixel[3] = (f32::from(pixel[3]) * opacity).round() as u8;
+    }
+    Ok(Self {
+      image,
+      anchors,
+      margin,
+    })
+  }
+
+  /// Alpha-blends the mark over `base`. Any part falling outside the base
+  /// (a mark bigger than the image, or a large margin) is clipped.
+  fn stamp(&self, base: &mut RgbaImage) {
+    let x = offset(
+      self.anchors.0,
+      base.width(),
+      self.image.width(),
+      self.margin,
+    );
+    let y = offset(
+      self.anchors.1,
+      base.height(),
+      self.image.height(),
+      self.margin,
+    );
+    imageops::overlay(base, &self.image, x, y);
+  }
+}
+
+/// Alpha-blends a watermark over a base image, both raw RGBA.
+///
+/// `position` is one of "top-left", "top", "top-right", "left", "center",
+/// "right", "bottom-left", "bottom" or "bottom-right" ("top-center" and
+/// friends are accepted too). `opacity` in [0, 1] scales the watermark's own
+/// alpha, and `margin` insets it from the edges it is anchored to.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn apply_watermark(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  base_data: Vec<u8>,
+  base_w: u32,
+  base_h: u32,
+  mark_data: Vec<u8>,
+  mark_w: u32,
+  mark_h: u32,
+  position: String,
+  opacity: f32,
+  margin: u32,
+) -> Result<Edited, AppError> {
+  traced("apply_watermark", async move {
+    let mut base = edit_source(&documents, doc_id, base_data, base_w, base_h)?;
+    let mark = Watermark::new(
+      rgba_from_raw(mark_data, mark_w, mark_h)?,
+      &position,
+      opacity,
+      margin,
+    )?;
+
+    mark.stamp(&mut base);
+    finish_edit(&documents, doc_id, base)
+  })
+  .await
+}
+
+/// Stamps the watermark at `mark_path` onto every supported image in
+/// `input_dir`, writing the results to `output_dir`, which must be a
+/// different directory.
+///
+/// The mark is decoded once for the whole batch. Each output keeps its
+/// source's format where that can be encoded and falls back to PNG
+/// otherwise. Progress and cancellation work as for `batch_convert`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn watermark_directory(
+  window: Window,
+  cancel: State<'_, BatchCancel>,
+  input_dir: String,
+  output_dir: String,
+  mark_path: String,
+  position: String,
+  opacity: f32,
+  margin: u32,
+  max_threads: Option<usize>,
+) -> Result<BatchResult, AppError> {
+  traced("watermark_directory", async move {
+    check_output_dir(&input_dir, &output_dir)?;
+    let mark = Watermark::new(
+      decode_file(Path::new(&mark_path))?.into_rgba8(),
+      &position,
+      opacity,
+      margin,
+    )?;
+    let images = batch_inputs(&input_dir)?;
+    let jobs = output_paths(Path::new(&output_dir), images, |source| {
+      source
+        .extension()
+        .and_then(|ext| output_extension(&ext.to_string_lossy()))
+        .unwrap_or("png")
+    });
+
+    run_batch(&window, &cancel, &jobs, max_threads, |source, output| {
+      let mut image = decode_file(source)?.into_rgba8();
+      mark.stamp(&mut image);
+
+      let extension = output.extension().unwrap_or_default().to_string_lossy();
+      encode_file(
+        output,
+        &DynamicImage::ImageRgba8(image),
+        &extension,
+        &EncodeOptions::default(),
+      )
+    })
+  })
+  .await
+}
+
+/// Composites an RGBA buffer onto a solid `background`, leaving every pixel
+/// opaque, as when exporting a transparent PNG as JPEG.
+#[tauri::command]
+pub async fn remove_alpha(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  background: [u8; 3],
+) -> Result<Edited, AppError> {
+  traced("remove_alpha", async move {
+    let mut image = edit_source(&documents, doc_id, data, width, height)?;
+    for pixel in image.pixels_mut() {
+      let alpha = u16::from(pixel[3]);
+      for (channel, &back) in pixel.0[..3].iter_mut().zip(&background) {
+        *channel =
+          ((u16::from(*channel) * alpha + u16::from(back) * (255 - alpha) + 127) / 255) as u8;
+      }
+      pixel[3] = 255;
+    }
+    finish_edit(&documents, doc_id, image)
+  })
+  .await
+}
+
+/// Makes pixels close to `key_color` fully transparent, like a basic
+/// chroma key.
+///
+/// A pixel matches when each of its colour channels is within `tolerance`
+/// of the key's, so a tolerance of 20 to 40 takes in the compression noise
+/// around a JPEG's background. 0 only matches the exact colour. Other
+/// pixels keep their alpha.
+#[tauri::command]
+pub async fn add_alpha_from_color(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  key_color: [u8; 3],
+  tolerance: u8,
+) -> Result<Edited, AppError> {
+  traced("add_alpha_from_color", async move {
+    let mut image = edit_source(&documents, doc_id, data, width, height)?;
+    for pixel in image.pixels_mut() {
+      let matches = pixel.0[..3]
+        .iter()
+        .zip(&key_color)
+        .all(|(&channel, &key)| channel.abs_diff(key) <= tolerance);
+      if matches {
+        pixel[3] = 0;
+      }
+    }
+    finish_edit(&documents, doc_id, image)
+  })
+  .await
+}
@@ -0,0 +1,150 @@
+//! Opening, listing and closing the documents behind the editor's tabs,
+//! and the plumbing that lets editing commands work on them in place.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use image::RgbaImage;
+use serde::Serialize;
+use tauri::State;
+
+use super::file::{decode_rgba, max_pixels};
+use super::preview::{store_image, Preview};
+use super::{rgba_from_raw, ImageData};
+use crate::documents::{DocumentId, DocumentInfo, Documents, Edit};
+use crate::error::AppError;
+use crate::logging::traced;
+use crate::preview::PreviewStore;
+use crate::sync::lock;
+
+/// What an editing command returns: the edited pixels when it was given a
+/// raw buffer, or just the document's new details when it edited one in
+/// place, so the pixels never cross the IPC bridge; `document_image` then
+/// gives a preview to draw.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Edited {
+  Image(ImageData),
+  Document(DocumentInfo),
+}
+
+/// A document from `open_document`, and a preview of its first state.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenedDocument {
+  #[serde(flatten)]
+  pub info: DocumentInfo,
+  pub preview: Preview,
+}
+
+/// Opens the image at `path` as a document named after the file, decoding
+/// it here so the pixels never cross the IPC bridge. `index` and
+/// `auto_orient` work as for `open_image`, and the pixel budget applies.
+#[tauri::command]
+pub async fn open_document(
+  documents: State<'_, Mutex<Documents>>,
+  previews: State<'_, Mutex<PreviewStore>>,
+  path: String,
+  index: Option<usize>,
+  auto_orient: Option<bool>,
+) -> Result<OpenedDocument, AppError> {
+  traced("open_document", async move {
+    let path = Path::new(&path);
+    let (image, _) = decode_rgba(path, index, max_pixels(), auto_orient.unwrap_or(false))?;
+    let preview = store_image(&previews, &image, None)?;
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let first = Edit::new(image)?;
+    let info = lock(&documents).create(name.into_owned(), first);
+    Ok(OpenedDocument { info, preview })
+  })
+  .await
+}
+
+/// Opens a document holding an RGBA buffer, which becomes its first undo
+/// state. `name` is what the tab shows, typically the file name.
+#[tauri::command]
+pub async fn create_document(
+  documents: State<'_, Mutex<Documents>>,
+  name: String,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+) -> Result<DocumentInfo, AppError> {
+  traced("create_document", async move {
+    let first = Edit::new(rgba_from_raw(data, width, height)?)?;
+    Ok(lock(&documents).create(name, first))
+  })
+  .await
+}
+
+/// Closes document `doc_id`, freeing its pixels and undo history, and
+/// returns the number of bytes freed.
+#[tauri::command]
+pub async fn close_document(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: DocumentId,
+) -> Result<usize, AppError> {
+  traced(
+    "close_document",
+    async move { lock(&documents).close(doc_id) },
+  )
+  .await
+}
+
+/// Every open document, oldest first, with how much memory each holds.
+#[tauri::command]
+pub async fn list_documents(
+  documents: State<'_, Mutex<Documents>>,
+) -> Result<Vec<DocumentInfo>, AppError> {
+  traced("list_documents", async move { Ok(lock(&documents).list()) }).await
+}
+
+/// A preview of document `doc_id`'s current state, for drawing its tab
+/// after an edit, undo or redo. `replaces` releases the tab's previous
+/// preview, as for `preview_file`.
+#[tauri::command]
+pub async fn document_image(
+  documents: State<'_, Mutex<Documents>>,
+  previews: State<'_, Mutex<PreviewStore>>,
+  doc_id: DocumentId,
+  replaces: Option<u64>,
+) -> Result<Preview, AppError> {
+  traced("document_image", async move {
+    let image = lock(&documents).get(doc_id)?.image().clone();
+    store_image(&previews, &image, replaces)
+  })
+  .await
+}
+
+/// The image an editing command starts from: document `doc_id`'s current
+/// state if given, in which case `data` may be empty and the dimensions
+/// are ignored, or else the raw RGBA buffer from JS.
+pub(crate) fn edit_source(
+  documents: &Mutex<Documents>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+) -> Result<RgbaImage, AppError> {
+  match doc_id {
+    Some(id) => Ok(lock(documents).get(id)?.image().clone()),
+    None => rgba_from_raw(data, width, height),
+  }
+}
+
+/// Hands back an editing command's result: it becomes document `doc_id`'s
+/// current state, recorded for undo, if given, or else is returned as
+/// pixels.
+pub(crate) fn finish_edit(
+  documents: &Mutex<Documents>,
+  doc_id: Option<DocumentId>,
+  image: RgbaImage,
+) -> Result<Edited, AppError> {
+  match doc_id {
+    Some(id) => {
+      let edit = Edit::new(image)?;
+      lock(documents).edit(id, edit).map(Edited::Document)
+    }
+    None => Ok(Edited::Image(ImageData::from_rgba(&image))),
+  }
+}
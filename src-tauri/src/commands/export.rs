@@ -0,0 +1,446 @@
+//! Exporting several images into one multi-page or multi-frame file, or
+//! one image at several sizes.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Cursor, Write};
+use std::path::Path;
+
+use color_quant::NeuQuant;
+use gif::{Encoder, Frame, Repeat};
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::codecs::jpeg::JpegDecoder;
+use image::imageops::{self, FilterType};
+use image::{
+  ColorType, ExtendedColorType, ImageDecoder, ImageError, ImageFormat, Rgb, RgbImage, RgbaImage,
+};
+use miniz_oxide::deflate::{compress_to_vec_zlib, CompressionLevel};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref};
+
+use super::file::{create_parent_dir, decode_file};
+use super::metadata::read_orientation;
+use super::rgba_from_raw;
+use crate::error::AppError;
+use crate::jpeg;
+use crate::logging::traced;
+
+/// A4 in PDF points (1/72 inch).
+const A4: (f32, f32) = (595.28, 841.89);
+/// US Letter in PDF points.
+const LETTER: (f32, f32) = (612.0, 792.0);
+
+/// The largest width and height an ICO entry can have.
+const MAX_ICO_SIZE: u32 = 256;
+
+/// NeuQuant sampling factor for GIF palettes, 1 (best) to 30 (fastest).
+const GIF_QUANT_SPEED: i32 = 10;
+
+/// How an image is scaled onto a fixed-size page.
+#[derive(Debug, Clone, Copy)]
+enum Fit {
+  /// Whole image visible, letterboxed.
+  Contain,
+  /// Page fully covered, overflow cropped.
+  Cover,
+}
+
+/// Image samples ready to drop into a PDF image XObject.
+struct PdfImage {
+  data: Vec<u8>,
+  filter: Filter,
+  width: u32,
+  height: u32,
+  grey: bool,
+}
+
+impl PdfImage {
+  /// Loads the image at `path` for embedding.
+  ///
+  /// Upright RGB and greyscale JPEGs are embedded byte-for-byte, since PDF
+  /// can hold DCT data as-is. Everything else is decoded, turned upright,
+  /// flattened onto white and stored losslessly.
+  fn load(path: &Path) -> Result<Self, AppError> {
+    let bytes = fs::read(path).map_err(|err| AppError::read(path, &err))?;
+
+    if image::guess_format(&bytes).ok() == Some(ImageFormat::Jpeg)
+      && jpeg::exif_orientation(&bytes).is_none_or(|tag| tag == 1)
+    {
+      if let Ok(decoder) = JpegDecoder::new(Cursor::new(&bytes)) {
+        let (width, height) = decoder.dimensions();
+        let grey = match decoder.color_type() {
+          ColorType::Rgb8 => Some(false),
+          ColorType::L8 => Some(true),
+          // CMYK and friends need colour-space handling PDF viewers are
+          // inconsistent about, so those get re-encoded.
+          _ => None,
+        };
+        if let Some(grey) = grey {
+          return Ok(Self {
+            data: bytes,
+            filter: Filter::DctDecode,
+            width,
+            height,
+            grey,
+          });
+        }
+      }
+    }
+
+    let image = read_orientation(path).apply(decode_file(path)?.into_rgba8());
+    let flattened = RgbImage::from_fn(image.width(), image.height(), |x, y| {
+      let [r, g, b, a] = image.get_pixel(x, y).0;
+      let over_white =
+        |c: u8| ((u16::from(c) * u16::from(a) + 255 * (255 - u16::from(a)) + 127) / 255) as u8;
+      Rgb([over_white(r), over_white(g), over_white(b)])
+    });
+
+    Ok(Self {
+      data: compress_to_vec_zlib(flattened.as_raw(), CompressionLevel::DefaultLevel as u8),
+      filter: Filter::FlateDecode,
+      width: flattened.width(),
+      height: flattened.height(),
+      grey: false,
+    })
+  }
+}
+
+/// Writes the images at `paths` to a PDF at `output`, one per page in the
+/// order given.
+///
+/// `page_size` is "a4", "letter", or "fit" to make each page exactly its
+/// image's size at 72 dpi. On a4 and letter pages `fit` decides the
+/// scaling: "contain" shows the whole image centred on the page, "cover"
+/// fills the page and crops whatever overflows. `fit` is ignored for "fit"
+/// pages.
+///
+/// JPEGs are embedded without re-encoding where possible; other formats are
+/// stored losslessly, with any transparency flattened onto white.
+#[tauri::command]
+pub async fn images_to_pdf(
+  paths: Vec<String>,
+  output: String,
+  page_size: String,
+  fit: String,
+) -> Result<(), AppError> {
+  traced("images_to_pdf", async move {
+    if paths.is_empty() {
+      return Err(AppError::InvalidArgument("no images to export".into()));
+    }
+    let page_size = match page_size.to_ascii_lowercase().as_str() {
+      "a4" => Some(A4),
+      "letter" => Some(LETTER),
+      "fit" => None,
+      other => {
+        return Err(AppError::InvalidArgument(format!(
+          "unknown page size: {}",
+          other
+        )))
+      }
+    };
+    let fit = match fit.to_ascii_lowercase().as_str() {
+      "contain" => Fit::Contain,
+      "cover" => Fit::Cover,
+      other => {
+        return Err(AppError::InvalidArgument(format!(
+          "unknown fit mode: {}",
+          other
+        )))
+      }
+    };
+
+    let mut pdf = Pdf::new();
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let image_name = Name(b"Im1");
+    // Each page takes three ids: page, image and content stream.
+    let page_ids: Vec<Ref> = (0..paths.len())
+      .map(|i| Ref::new(3 + 3 * i as i32))
+      .collect();
+
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf
+      .pages(page_tree_id)
+      .kids(page_ids.iter().copied())
+      .count(paths.len() as i32);
+
+    for (path, &page_id) in paths.iter().zip(&page_ids) {
+      let image = PdfImage::load(Path::new(path))?;
+      let image_id = Ref::new(page_id.get() + 1);
+      let content_id = Ref::new(page_id.get() + 2);
+
+      let (image_w, image_h) = (image.width as f32, image.height as f32);
+      let ((page_w, page_h), scale) = match page_size {
+        None => ((image_w, image_h), 1.0),
+        Some((page_w, page_h)) => {
+          let (scale_x, scale_y) = (page_w / image_w, page_h / image_h);
+          let scale = match fit {
+            Fit::Contain => scale_x.min(scale_y),
+            Fit::Cover => scale_x.max(scale_y),
+          };
+          ((page_w, page_h), scale)
+        }
+      };
+      let (w, h) = (image_w * scale, image_h * scale);
+
+      let mut page = pdf.page(page_id);
+      page.media_box(Rect::new(0.0, 0.0, page_w, page_h));
+      page.parent(page_tree_id);
+      page.contents(content_id);
+      page.resources().x_objects().pair(image_name, image_id);
+      page.finish();
+
+      let mut xobject = pdf.image_xobject(image_id, &image.data);
+      xobject.filter(image.filter);
+      xobject.width(image.width as i32);
+      xobject.height(image.height as i32);
+      if image.grey {
+        xobject.color_space().device_gray();
+      } else {
+        xobject.color_space().device_rgb();
+      }
+      xobject.bits_per_component(8);
+      xobject.finish();
+
+      // Image XObjects are a 1x1 unit square, so the transform both sizes
+      // and centres the image. Anything outside the media box is cropped.
+      let mut content = Content::new();
+      content.save_state();
+      content.transform([w, 0.0, 0.0, h, (page_w - w) / 2.0, (page_h - h) / 2.0]);
+      content.x_object(image_name);
+      content.restore_state();
+      pdf.stream(content_id, &content.finish());
+    }
+
+    let output = Path::new(&output);
+    create_parent_dir(output)?;
+    fs::write(output, pdf.finish()).map_err(|err| AppError::write(output, &err))
+  })
+  .await
+}
+
+/// Encodes the images at `frame_paths` as an animated GIF at `output`,
+/// showing each for `delay_ms`.
+///
+/// Every frame is scaled to the size of the first. GIF has no partial
+/// transparency, so alpha is rounded to fully opaque or fully clear. GIF
+/// stores delays in hundredths of a second, so `delay_ms` is rounded to the
+/// nearest 10 ms;
+/// note most browsers slow anything under 20 ms down to 100 ms.
+/// `loop_forever` makes the animation repeat, otherwise it plays once.
+///
+/// Each frame gets its own 256-colour palette. `dither` spreads the
+/// quantisation error with Floyd-Steinberg diffusion, which trades banding
+/// in smooth gradients for fine grain, and some file size.
+#[tauri::command]
+pub async fn create_gif(
+  frame_paths: Vec<String>,
+  output: String,
+  delay_ms: u16,
+  loop_forever: bool,
+  dither: Option<bool>,
+) -> Result<(), AppError> {
+  traced("create_gif", async move {
+    let Some((first, rest)) = frame_paths.split_first() else {
+      return Err(AppError::InvalidArgument("no frames to encode".into()));
+    };
+    let first = decode_file(Path::new(first))?.into_rgba8();
+    let (width, height) = first.dimensions();
+    let (gif_w, gif_h) = match (u16::try_from(width), u16::try_from(height)) {
+      (Ok(w), Ok(h)) => (w, h),
+      _ => {
+        return Err(AppError::InvalidArgument(format!(
+          "{}x{} is too large for a GIF; frames can be at most 65535 pixels a side",
+          width, height
+        )))
+      }
+    };
+
+    let output = Path::new(&output);
+    create_parent_dir(output)?;
+    let file = File::create(output).map_err(|err| AppError::write(output, &err))?;
+    let gif_error = |err: gif::EncodingError| match err {
+      gif::EncodingError::Io(err) => AppError::write(output, &err),
+      err => AppError::EncodeFailed(format!("failed to encode {}: {}", output.display(), err)),
+    };
+
+    let mut encoder = Encoder::new(BufWriter::new(file), gif_w, gif_h, &[]).map_err(gif_error)?;
+    let repeat = if loop_forever {
+      Repeat::Infinite
+    } else {
+      Repeat::Finite(0)
+    };
+    encoder.set_repeat(repeat).map_err(gif_error)?;
+
+    let delay = delay_ms.saturating_add(5) / 10;
+    let dither = dither.unwrap_or(false);
+    let mut write_frame = |mut frame: RgbaImage| {
+      // GIF transparency is all or nothing, and only one palette entry can be
+      // transparent, so every see-through pixel must be the same colour.
+      for pixel in frame.pixels_mut() {
+        pixel.0 = if pixel[3] < 128 {
+          [0, 0, 0, 0]
+        } else {
+          [pixel[0], pixel[1], pixel[2], 255]
+        };
+      }
+
+      let mut frame = if dither {
+        dithered_frame(frame, gif_w, gif_h)
+      } else {
+        Frame::from_rgba_speed(gif_w, gif_h, &mut frame.into_raw(), GIF_QUANT_SPEED)
+      };
+      frame.delay = delay;
+      encoder.write_frame(&frame).map_err(gif_error)
+    };
+
+    write_frame(first)?;
+    for path in rest {
+      let mut frame = decode_file(Path::new(path))?.into_rgba8();
+      if frame.dimensions() != (width, height) {
+        frame = imageops::resize(&frame, width, height, FilterType::Lanczos3);
+      }
+      write_frame(frame)?;
+    }
+
+    encoder
+      .into_inner()
+      .and_then(|mut writer| writer.flush())
+      .map_err(|err| AppError::write(output, &err))
+  })
+  .await
+}
+
+/// Packs an RGBA buffer into an icon at `output`, scaled to each of
+/// `sizes`, such as 16, 32, 48 and 256 for a favicon.
+///
+/// A non-square source is cropped to its centred square first. ICO entries
+/// are at most 256 pixels a side; each is stored as a PNG, which every
+/// browser and Windows since Vista reads. Repeated sizes are written once.
+#[tauri::command]
+pub async fn export_ico(
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  sizes: Vec<u32>,
+  output: String,
+) -> Result<(), AppError> {
+  traced("export_ico", async move {
+    if sizes.is_empty() {
+      return Err(AppError::InvalidArgument("no icon sizes given".into()));
+    }
+    let invalid: Vec<String> = sizes
+      .iter()
+      .filter(|&&size| size == 0 || size > MAX_ICO_SIZE)
+      .map(|size| size.to_string())
+      .collect();
+    if !invalid.is_empty() {
+      return Err(AppError::InvalidArgument(format!(
+        "icon sizes must be 1 to {} pixels, got {}",
+        MAX_ICO_SIZE,
+        invalid.join(", ")
+      )));
+    }
+    let mut sizes = sizes;
+    sizes.sort_unstable();
+    sizes.dedup();
+
+    let image = rgba_from_raw(data, width, height)?;
+    let side = width.min(height);
+    if side == 0 {
+      return Err(AppError::InvalidArgument(format!(
+        "can't make an icon from a {}x{} image",
+        width, height
+      )));
+    }
+    let square =
+      imageops::crop_imm(&image, (width - side) / 2, (height - side) / 2, side, side).to_image();
+
+    let output = Path::new(&output);
+    let encode_error = |err: ImageError| match err {
+      ImageError::IoError(err) => AppError::write(output, &err),
+      err => AppError::EncodeFailed(format!("failed to encode {}: {}", output.display(), err)),
+    };
+    let mut frames = Vec::with_capacity(sizes.len());
+    for &size in &sizes {
+      let scaled = if size == side {
+        square.clone()
+      } else {
+        imageops::resize(&square, size, size, FilterType::Lanczos3)
+      };
+      frames.push(
+        IcoFrame::as_png(scaled.as_raw(), size, size, ExtendedColorType::Rgba8)
+          .map_err(encode_error)?,
+      );
+    }
+
+    create_parent_dir(output)?;
+    let file = File::create(output).map_err(|err| AppError::write(output, &err))?;
+    let mut writer = BufWriter::new(file);
+    IcoEncoder::new(&mut writer)
+      .encode_images(&frames)
+      .map_err(encode_error)?;
+    writer.flush().map_err(|err| AppError::write(output, &err))
+  })
+  .await
+}
+
+/// Quantises `image` to a GIF frame with Floyd-Steinberg dithering.
+///
+/// `image` must only have fully opaque or fully transparent pixels. The
+/// transparent ones take no part in the palette or the error diffusion.
+fn dithered_frame(image: RgbaImage, width: u16, height: u16) -> Frame<'static> {
+  let opaque: Vec<u8> = image
+    .pixels()
+    .filter(|pixel| pixel[3] == 255)
+    .flat_map(|pixel| pixel.0)
+    .collect();
+  let has_transparency = opaque.len() / 4 < image.pixels().len();
+
+  // Keep the last palette slot free for transparency when it's needed.
+  let colors = if has_transparency { 255 } else { 256 };
+  let quant = (!opaque.is_empty()).then(|| NeuQuant::new(GIF_QUANT_SPEED, colors, &opaque));
+  let mut palette = quant
+    .as_ref()
+    .map(NeuQuant::color_map_rgb)
+    .unwrap_or_default();
+  let transparent = has_transparency.then(|| {
+    let index = palette.len() / 3;
+    palette.extend_from_slice(&[0, 0, 0]);
+    index as u8
+  });
+
+  let w = image.width() as usize;
+  let mut indices = Vec::with_capacity(image.pixels().len());
+  // Error carried into the current and the next row, per colour channel.
+  let mut current = vec![[0.0f32; 3]; w + 2];
+  let mut next = vec![[0.0f32; 3]; w + 2];
+
+  for row in image.rows() {
+    for (x, pixel) in row.enumerate() {
+      let (Some(quant), 255) = (&quant, pixel[3]) else {
+        indices.push(transparent.unwrap_or(0));
+        continue;
+      };
+
+      let mut wanted = [0u8, 0, 0, 255];
+      for (c, value) in wanted[..3].iter_mut().enumerate() {
+        *value = (f32::from(pixel[c]) + current[x + 1][c])
+          .round()
+          .clamp(0.0, 255.0) as u8;
+      }
+      let index = quant.index_of(&wanted);
+      indices.push(index as u8);
+
+      for c in 0..3 {
+        let err = f32::from(wanted[c]) - f32::from(palette[index * 3 + c]);
+        current[x + 2][c] += err * 7.0 / 16.0;
+        next[x][c] += err * 3.0 / 16.0;
+        next[x + 1][c] += err * 5.0 / 16.0;
+        next[x + 2][c] += err / 16.0;
+      }
+    }
+    std::mem::swap(&mut current, &mut next);
+    next.fill([0.0; 3]);
+  }
+
+  Frame::from_palette_pixels(width, height, indices, palette, transparent)
+}
@@ -0,0 +1,587 @@
+//! Loading and saving images on disk.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "avif")]
+use image::codecs::avif::AvifEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::error::{EncodingError, ImageFormatHint};
+use image::{DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader, Limits, RgbaImage};
+use serde::{Deserialize, Serialize};
+use turbojpeg::{Compressor, PixelFormat, Subsamp};
+
+use super::metadata::read_orientation;
+use super::{rgba_from_raw, ImageData};
+use crate::error::AppError;
+use crate::heif;
+use crate::logging::traced;
+
+const DEFAULT_JPEG_QUALITY: u8 = 90;
+#[cfg(feature = "avif")]
+const DEFAULT_AVIF_QUALITY: u8 = 80;
+/// rav1e speed preset, 1 (slowest, smallest) to 10 (fastest).
+#[cfg(feature = "avif")]
+const AVIF_SPEED: u8 = 6;
+
+/// Default for `set_max_pixels`: 100 megapixels, 400 MB as RGBA.
+const DEFAULT_MAX_PIXELS: u64 = 100_000_000;
+/// The most memory any supported format needs per pixel while decoding
+/// (32-bit float RGBA). The pixel budget times this caps both the file size
+/// and the decoder's allocations.
+const BYTES_PER_PIXEL: u64 = 16;
+
+/// The pixel budget every decode is checked against, before any pixel
+/// data is allocated.
+static MAX_PIXELS: AtomicU64 = AtomicU64::new(DEFAULT_MAX_PIXELS);
+
+/// An image from `open_image`, with its colour profile if one was asked
+/// for.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenedImage {
+  #[serde(flatten)]
+  pub image: ImageData,
+  /// The embedded ICC profile, as raw bytes for `convert_to_srgb`. `None`
+  /// when the file has none, meaning it is sRGB.
+  pub icc_profile: Option<Vec<u8>>,
+}
+
+/// Decodes the image at `path` and returns it as RGBA.
+///
+/// HEIF/HEIC files (with the `heif` feature) can hold several images; the
+/// primary one is returned unless `index` picks another. For every other
+/// format only index 0 exists.
+///
+/// Pixels are returned as stored, without colour management. Set
+/// `include_icc` to also get the embedded ICC profile, which PNG, JPEG,
+/// WebP, TIFF and HEIF files can carry.
+///
+/// Set `auto_orient` to apply the EXIF orientation tag, as `auto_orient`
+/// does, so the image comes back upright and the tag no longer applies to
+/// it. HEIF images are always upright.
+///
+/// Images over the pixel budget (see `set_max_pixels`) fail with
+/// `TooLarge` before they are decoded. Pass that error's `required` as
+/// `max_pixels` to load the image anyway; it raises the budget for this
+/// call only.
+#[tauri::command]
+pub async fn open_image(
+  path: String,
+  index: Option<usize>,
+  include_icc: Option<bool>,
+  max_pixels: Option<u64>,
+  auto_orient: Option<bool>,
+) -> Result<OpenedImage, AppError> {
+  traced("open_image", async move {
+    let path = Path::new(&path);
+    let max_pixels = max_pixels.unwrap_or_else(self::max_pixels);
+    let (image, icc_profile) = decode_rgba(path, index, max_pixels, auto_orient.unwrap_or(false))?;
+    Ok(OpenedImage {
+      image: ImageData::from_rgba(&image),
+      icc_profile: icc_profile.filter(|_| include_icc.unwrap_or(false)),
+    })
+  })
+  .await
+}
+
+/// Sets the pixel budget for every image decoded from now on, in pixels
+/// (width times height). The default is 100 megapixels.
+#[tauri::command]
+pub async fn set_max_pixels(max_pixels: u64) -> Result<(), AppError> {
+  traced("set_max_pixels", async move {
+    if max_pixels == 0 {
+      return Err(AppError::InvalidArgument(
+        "the pixel budget must be at least 1".into(),
+      ));
+    }
+    MAX_PIXELS.store(max_pixels, Ordering::Relaxed);
+    Ok(())
+  })
+  .await
+}
+
+/// Encodes an RGBA buffer to `path` as PNG, JPEG, WebP or (when built with
+/// the `avif` feature) AVIF, with the settings in `options`.
+///
+/// A setting the format can't honour, such as a progressive PNG, fails with
+/// `InvalidArgument` naming it instead of being ignored.
+#[tauri::command]
+pub async fn save_image(
+  path: String,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  format: String,
+  options: Option<EncodeOptions>,
+) -> Result<(), AppError> {
+  traced("save_image", async move {
+    let image = DynamicImage::ImageRgba8(rgba_from_raw(data, width, height)?);
+    encode_file(
+      Path::new(&path),
+      &image,
+      &format,
+      &options.unwrap_or_default(),
+    )
+  })
+  .await
+}
+
+/// Format-specific settings for `encode_file`. Every field is optional
+/// from JS.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct EncodeOptions {
+  /// 1-100, for JPEG, AVIF and lossy WebP.
+  pub quality: Option<u8>,
+  /// WebP is lossy when a `quality` is given and lossless otherwise; this
+  /// asks for lossless outright. PNG is always lossless, and JPEG and AVIF
+  /// never are.
+  pub lossless: bool,
+  /// Progressive JPEG, which shows a rough version of the whole image while
+  /// it downloads. Off by default.
+  pub progressive: bool,
+  /// JPEG chroma subsampling, 4:4:4 by default.
+  pub subsampling: Option<Subsampling>,
+}
+
+/// How much colour detail a JPEG keeps relative to brightness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Subsampling {
+  /// Full colour resolution, which keeps text and hard edges crisp.
+  #[serde(rename = "4:4:4")]
+  Chroma444,
+  /// Half horizontal colour resolution.
+  #[serde(rename = "4:2:2")]
+  Chroma422,
+  /// Half horizontal and vertical colour resolution, the smallest files and
+  /// usually invisible in photos.
+  #[serde(rename = "4:2:0")]
+  Chroma420,
+}
+
+/// Writes `image` to `path` in the named format, creating parent
+/// directories as needed.
+pub(crate) fn encode_file(
+  path: &Path,
+  image: &DynamicImage,
+  format: &str,
+  options: &EncodeOptions,
+) -> Result<(), AppError> {
+  if output_extension(format).is_none() {
+    return Err(AppError::UnsupportedFormat(format!(
+      "unsupported output format: {}",
+      format
+    )));
+  }
+
+  check_options(format, options)?;
+
+  create_parent_dir(path)?;
+  let file = File::create(path).map_err(|err| AppError::write(path, &err))?;
+  let mut writer = BufWriter::new(file);
+  let quality = options.quality.map(|quality| quality.clamp(1, 100));
+
+  let result = match format.to_ascii_lowercase().as_str() {
+    "png" => image.write_with_encoder(PngEncoder::new(&mut writer)),
+    "jpg" | "jpeg" => encode_jpeg(image, quality.unwrap_or(DEFAULT_JPEG_QUALITY), options)
+      .and_then(|encoded| writer.write_all(&encoded).map_err(ImageError::IoError)),
+    "webp" => match quality {
+      Some(quality) if !options.lossless => {
+        let rgba = image.to_rgba8();
+        let encoded = webp::Encoder::from_rgba(rgba.as_raw(), rgba.width(), rgba.height())
+          .encode(quality.into());
+        writer.write_all(&encoded).map_err(ImageError::IoError)
+      }
+      _ => image.write_with_encoder(WebPEncoder::new_lossless(&mut writer)),
+    },
+    #[cfg(feature = "avif")]
+    "avif" => image.write_with_encoder(AvifEncoder::new_with_speed_quality(
+      &mut writer,
+      AVIF_SPEED,
+      quality.unwrap_or(DEFAULT_AVIF_QUALITY),
+    )),
+    other => unreachable!("unchecked output format {}", other),
+  };
+
+  match result {
+    Ok(()) => writer.flush().map_err(|err| AppError::write(path, &err)),
+    Err(ImageError::IoError(err)) => Err(AppError::write(path, &err)),
+    Err(err) => Err(AppError::EncodeFailed(format!(
+      "failed to encode {}: {}",
+      path.display(),
+      err
+    ))),
+  }
+}
+
+/// Fails with `InvalidArgument` naming a setting the encoder for `format`
+/// can't honour, rather than saving the image without it.
+fn check_options(format: &str, options: &EncodeOptions) -> Result<(), AppError> {
+  let format = format.to_ascii_lowercase();
+  let jpeg = matches!(format.as_str(), "jpg" | "jpeg");
+  let unsupported = if options.lossless && !matches!(format.as_str(), "png" | "webp") {
+    Some("lossless compression")
+  } else if options.quality.is_some() && format == "png" {
+    Some("a quality")
+  } else if options.quality.is_some() && options.lossless {
+    Some("both a quality and lossless compression")
+  } else if options.progressive && !jpeg {
+    Some("progressive encoding")
+  } else if options.subsampling.is_some() && !jpeg {
+    Some("chroma subsampling")
+  } else {
+    None
+  };
+  match unsupported {
+    Some(setting) => Err(AppError::InvalidArgument(format!(
+      "{} can't be saved with {}",
+      format.to_ascii_uppercase(),
+      setting
+    ))),
+    None => Ok(()),
+  }
+}
+
+/// Encodes `image` as JPEG with libjpeg-turbo, which unlike the `image`
+/// crate's encoder can subsample chroma and write progressive files.
+fn encode_jpeg(
+  image: &DynamicImage,
+  quality: u8,
+  options: &EncodeOptions,
+) -> Result<Vec<u8>, ImageError> {
+  let jpeg_error = |err: turbojpeg::Error| {
+    ImageError::Encoding(EncodingError::new(
+      ImageFormatHint::Exact(ImageFormat::Jpeg),
+      err,
+    ))
+  };
+  let subsamp = match options.subsampling.unwrap_or(Subsampling::Chroma444) {
+    Subsampling::Chroma444 => Subsamp::None,
+    Subsampling::Chroma422 => Subsamp::Sub2x1,
+    Subsampling::Chroma420 => Subsamp::Sub2x2,
+  };
+
+  let mut compressor = Compressor::new().map_err(jpeg_error)?;
+  compressor
+    .set_quality(i32::from(quality))
+    .and_then(|_| compressor.set_subsamp(subsamp))
+    .and_then(|_| compressor.set_progressive(options.progressive))
+    .map_err(jpeg_error)?;
+
+  // JPEG has no alpha channel.
+  let rgb = image.to_rgb8();
+  let width = rgb.width() as usize;
+  compressor
+    .compress_to_vec(turbojpeg::Image {
+      pixels: rgb.as_raw().as_slice(),
+      width,
+      pitch: width * 3,
+      height: rgb.height() as usize,
+      format: PixelFormat::RGB,
+    })
+    .map_err(jpeg_error)
+}
+
+/// Creates the directory `path` will be written into, if it is missing.
+pub(crate) fn create_parent_dir(path: &Path) -> Result<(), AppError> {
+  match path.parent() {
+    Some(parent) => fs::create_dir_all(parent)
+      .map_err(|err| AppError::Io(format!("failed to create {}: {}", parent.display(), err))),
+    None => Ok(()),
+  }
+}
+
+/// File extension used when writing `format`, or `None` if we can't encode it.
+pub(crate) fn output_extension(format: &str) -> Option<&'static str> {
+  match format.to_ascii_lowercase().as_str() {
+    "png" => Some("png"),
+    "jpg" | "jpeg" => Some("jpg"),
+    "webp" => Some("webp"),
+    #[cfg(feature = "avif")]
+    "avif" => Some("avif"),
+    _ => None,
+  }
+}
+
+/// Whether `path` has the extension of a format `decode_file` can read.
+pub(crate) fn is_supported_image(path: &Path) -> bool {
+  matches!(
+    ImageFormat::from_path(path),
+    Ok(
+      ImageFormat::Png
+        | ImageFormat::Jpeg
+        | ImageFormat::Bmp
+        | ImageFormat::Tiff
+        | ImageFormat::WebP
+    )
+  ) || (cfg!(feature = "heif")
+    && path
+      .extension()
+      .is_some_and(|ext| ext.eq_ignore_ascii_case("heic") || ext.eq_ignore_ascii_case("heif")))
+}
+
+/// Reads and decodes an image, sniffing the format from the file contents.
+///
+/// Missing files and unrecognised formats get their own messages so the UI
+/// can tell them apart from generic read failures. Images over the pixel
+/// budget are rejected with `TooLarge`.
+pub(crate) fn decode_file(path: &Path) -> Result<DynamicImage, AppError> {
+  decode_image(path, None, max_pixels()).map(|(image, _)| image)
+}
+
+/// Decodes image `index` of the file at `path` to RGBA, optionally upright,
+/// along with its embedded ICC profile. Shared by `open_image` and
+/// `open_document`.
+pub(crate) fn decode_rgba(
+  path: &Path,
+  index: Option<usize>,
+  max_pixels: u64,
+  auto_orient: bool,
+) -> Result<(RgbaImage, Option<Vec<u8>>), AppError> {
+  let (image, icc_profile) = decode_image(path, index, max_pixels)?;
+  let mut image = image.into_rgba8();
+  if auto_orient {
+    image = read_orientation(path).apply(image);
+  }
+  Ok((image, icc_profile))
+}
+
+fn decode_image(
+  path: &Path,
+  index: Option<usize>,
+  max_pixels: u64,
+) -> Result<(DynamicImage, Option<Vec<u8>>), AppError> {
+  let max_file_len = check_file_len(path, max_pixels)?;
+
+  if heif::is_heif(path) {
+    #[cfg(feature = "heif")]
+    return heif::decode(path, index, max_pixels)
+      .map(|(image, icc_profile)| (DynamicImage::ImageRgba8(image), icc_profile));
+    #[cfg(not(feature = "heif"))]
+    return Err(AppError::UnsupportedFormat(format!(
+      "unsupported image format: {}: this build has no HEIF support",
+      path.display()
+    )));
+  }
+  if let Some(index @ 1..) = index {
+    return Err(AppError::NotFound(format!(
+      "{} has a single image, no image at index {}",
+      path.display(),
+      index
+    )));
+  }
+
+  let decode_error = |err| match err {
+    ImageError::Unsupported(err) => AppError::UnsupportedFormat(format!(
+      "unsupported image format: {}: {}",
+      path.display(),
+      err
+    )),
+    ImageError::IoError(err) => AppError::read(path, &err),
+    err => AppError::DecodeFailed(format!("failed to decode {}: {}", path.display(), err)),
+  };
+  let mut reader = open_reader(path)?;
+  let mut limits = Limits::no_limits();
+  limits.max_alloc = Some(max_file_len);
+  reader.limits(limits);
+  let mut decoder = reader.into_decoder().map_err(decode_error)?;
+  let (width, height) = decoder.dimensions();
+  check_pixels(path.display(), width, height, max_pixels)?;
+  // A malformed profile shouldn't stop the pixels from loading.
+  let icc_profile = decoder.icc_profile().ok().flatten();
+  let image = DynamicImage::from_decoder(decoder).map_err(decode_error)?;
+  Ok((image, icc_profile))
+}
+
+/// Fails with `TooLarge` if the file at `path` is too big to read into
+/// memory under the pixel budget `max_pixels`, and otherwise returns the
+/// largest size that would be allowed.
+pub(crate) fn check_file_len(path: &Path, max_pixels: u64) -> Result<u64, AppError> {
+  let file_len = fs::metadata(path)
+    .map_err(|err| AppError::read(path, &err))?
+    .len();
+  let max_file_len = max_pixels.saturating_mul(BYTES_PER_PIXEL);
+  if file_len > max_file_len {
+    return Err(AppError::TooLarge {
+      message: format!(
+        "{} is {:.1} MB, over the {:.1} MB limit",
+        path.display(),
+        file_len as f64 / 1e6,
+        max_file_len as f64 / 1e6
+      ),
+      required: file_len.div_ceil(BYTES_PER_PIXEL),
+      limit: max_pixels,
+    });
+  }
+  Ok(max_file_len)
+}
+
+/// The pixel budget set by `set_max_pixels`.
+pub(crate) fn max_pixels() -> u64 {
+  MAX_PIXELS.load(Ordering::Relaxed)
+}
+
+/// Fails with `TooLarge` if a `width` by `height` image is over the pixel
+/// budget `max_pixels`. `subject` names the image in the message.
+pub(crate) fn check_pixels(
+  subject: impl fmt::Display,
+  width: u32,
+  height: u32,
+  max_pixels: u64,
+) -> Result<(), AppError> {
+  let pixels = u64::from(width) * u64::from(height);
+  if pixels <= max_pixels {
+    return Ok(());
+  }
+  Err(AppError::TooLarge {
+    message: format!(
+      "{} is {}x{}, {:.1} megapixels, over the {:.1} megapixel limit",
+      subject,
+      width,
+      height,
+      pixels as f64 / 1e6,
+      max_pixels as f64 / 1e6
+    ),
+    required: pixels,
+    limit: max_pixels,
+  })
+}
+
+/// Reads the width and height of the image at `path` from its header,
+/// without decoding the pixel data.
+#[tauri::command]
+pub async fn image_dimensions(path: String) -> Result<(u32, u32), AppError> {
+  traced("image_dimensions", async move {
+    read_dimensions(Path::new(&path))
+  })
+  .await
+}
+
+/// The width and height in the header of the image at `path`.
+pub(crate) fn read_dimensions(path: &Path) -> Result<(u32, u32), AppError> {
+  open_reader(path)?
+    .into_dimensions()
+    .map_err(|err| match err {
+      ImageError::Unsupported(err) => AppError::UnsupportedFormat(format!(
+        "unsupported image format: {}: {}",
+        path.display(),
+        err
+      )),
+      ImageError::IoError(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+        AppError::DecodeFailed(format!("truncated image header: {}", path.display()))
+      }
+      ImageError::IoError(err) => AppError::read(path, &err),
+      err => AppError::DecodeFailed(format!("corrupt image header: {}: {}", path.display(), err)),
+    })
+}
+
+/// The format of the image at `path`, sniffed from its contents.
+pub(crate) fn sniff_format(path: &Path) -> Result<ImageFormat, AppError> {
+  let reader = open_reader(path)?;
+  // `open_reader` has already rejected unknown formats.
+  reader
+    .format()
+    .ok_or_else(|| AppError::UnsupportedFormat(format!("unknown image format: {}", path.display())))
+}
+
+/// Opens `path` for decoding with its format sniffed from the contents.
+fn open_reader(path: &Path) -> Result<ImageReader<BufReader<File>>, AppError> {
+  let reader = ImageReader::open(path)
+    .and_then(|reader| reader.with_guessed_format())
+    .map_err(|err| AppError::read(path, &err))?;
+
+  if reader.format().is_none() {
+    return Err(AppError::UnsupportedFormat(format!(
+      "unknown image format: {}",
+      path.display()
+    )));
+  }
+  Ok(reader)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use image::{Rgba, RgbaImage};
+
+  #[test]
+  fn lossless_webp_round_trips_exactly() {
+    // Flat regions, hard edges and partial transparency, like a screenshot.
+    let original = RgbaImage::from_fn(64, 48, |x, y| match (x / 16, y / 12) {
+      (0, _) => Rgba([255, 255, 255, 255]),
+      (1, row) => Rgba([30, 60 * row as u8, 200, 255]),
+      (2, _) if (x + y) % 2 == 0 => Rgba([0, 0, 0, 255]),
+      _ => Rgba([x as u8 * 4, y as u8 * 5, 90, 128]),
+    });
+
+    let path =
+      std::env::temp_dir().join(format!("image-pro-{}-roundtrip.webp", std::process::id()));
+    let options = EncodeOptions {
+      lossless: true,
+      ..Default::default()
+    };
+    encode_file(
+      &path,
+      &DynamicImage::ImageRgba8(original.clone()),
+      "webp",
+      &options,
+    )
+    .unwrap();
+    let decoded = decode_file(&path).unwrap().to_rgba8();
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(decoded, original);
+  }
+
+  #[test]
+  fn settings_the_encoder_cant_honour_are_refused() {
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([10, 20, 30, 255])));
+    let path = std::env::temp_dir().join(format!("image-pro-{}-refused", std::process::id()));
+    let refused = [
+      (
+        "png",
+        EncodeOptions {
+          quality: Some(80),
+          ..Default::default()
+        },
+      ),
+      (
+        "webp",
+        EncodeOptions {
+          quality: Some(80),
+          lossless: true,
+          ..Default::default()
+        },
+      ),
+      (
+        "jpg",
+        EncodeOptions {
+          lossless: true,
+          ..Default::default()
+        },
+      ),
+      (
+        "webp",
+        EncodeOptions {
+          progressive: true,
+          ..Default::default()
+        },
+      ),
+    ];
+    for (format, options) in refused {
+      let result = encode_file(&path, &image, format, &options);
+      assert!(
+        matches!(result, Err(AppError::InvalidArgument(_))),
+        "{} with {:?}",
+        format,
+        options
+      );
+    }
+    assert!(!path.exists());
+  }
+}
@@ -0,0 +1,68 @@
+//! Undo and redo, kept separately for each open document.
+
+use std::sync::Mutex;
+
+use tauri::State;
+
+use super::{rgba_from_raw, ImageData};
+use crate::documents::{DocumentId, Documents, Edit};
+use crate::error::AppError;
+use crate::logging::{traced, traced_sync};
+use crate::sync::lock;
+
+/// Makes an edited image the current state of document `doc_id`,
+/// recording it for undo.
+#[tauri::command]
+pub async fn push_history(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: DocumentId,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+) -> Result<(), AppError> {
+  traced("push_history", async move {
+    let edit = Edit::new(rgba_from_raw(data, width, height)?)?;
+    lock(&documents).edit(doc_id, edit)?;
+    Ok(())
+  })
+  .await
+}
+
+/// Returns document `doc_id` to the state before the current one.
+#[tauri::command]
+pub async fn undo(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: DocumentId,
+) -> Result<ImageData, AppError> {
+  traced("undo", async move {
+    let image = lock(&documents).get_mut(doc_id)?.undo()?.clone();
+    Ok(ImageData::from_rgba(&image))
+  })
+  .await
+}
+
+/// Returns document `doc_id` to the state after the current one, if an
+/// undo left one.
+#[tauri::command]
+pub async fn redo(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: DocumentId,
+) -> Result<ImageData, AppError> {
+  traced("redo", async move {
+    let image = lock(&documents).get_mut(doc_id)?.redo()?.clone();
+    Ok(ImageData::from_rgba(&image))
+  })
+  .await
+}
+
+/// Changes how many states each document keeps, dropping the oldest if
+/// needed. Documents opened later use the new depth too.
+#[tauri::command]
+pub fn set_history_depth(
+  documents: State<'_, Mutex<Documents>>,
+  depth: usize,
+) -> Result<(), AppError> {
+  traced_sync("set_history_depth", || {
+    lock(&documents).set_history_depth(depth);
+  })
+}
@@ -0,0 +1,37 @@
+//! Finding the log file and changing how much goes into it.
+
+use std::str::FromStr;
+
+use tauri::State;
+use tracing::level_filters::LevelFilter;
+
+use crate::error::AppError;
+use crate::logging::{traced, Logging};
+
+/// The directory the log is written to, for the user to attach to a bug
+/// report. It holds one file per day, named by date, for the last week.
+#[tauri::command]
+pub async fn get_log_path(logging: State<'_, Logging>) -> Result<String, AppError> {
+  traced("get_log_path", async move {
+    logging
+      .dir()
+      .map(|dir| dir.display().to_string())
+      .ok_or_else(|| AppError::NotFound("the log directory could not be opened".into()))
+  })
+  .await
+}
+
+/// Sets the most verbose level written to the log: `error`, `warn`,
+/// `info` (the default), `debug`, `trace` or `off`. It applies until the
+/// app is restarted.
+#[tauri::command]
+pub async fn set_log_level(logging: State<'_, Logging>, level: String) -> Result<(), AppError> {
+  traced("set_log_level", async move {
+    let filter = LevelFilter::from_str(&level)
+      .map_err(|_| AppError::InvalidArgument(format!("unknown log level: {}", level)))?;
+    logging.set_level(filter)?;
+    tracing::info!("log level set to {}", filter);
+    Ok(())
+  })
+  .await
+}
@@ -0,0 +1,88 @@
+//! Edits applied to encoded files without a decode/re-encode round trip.
+
+use std::fs;
+use std::path::Path;
+
+use image::ImageFormat;
+use turbojpeg::{OwnedBuf, Transform, TransformOp};
+
+use crate::error::AppError;
+use crate::jpeg;
+use crate::logging::traced;
+use crate::orientation::Orientation;
+
+/// Rotates a JPEG clockwise by `degrees` in the DCT domain and writes it
+/// back in place, so no generation loss is introduced.
+///
+/// Any existing EXIF orientation is folded into the same transform and the
+/// tag reset to 1, so viewers show the rotated image without rotating it a
+/// second time. Partial MCU blocks on the edges that can't be moved are
+/// trimmed, which can shave a few pixels off the right or bottom.
+#[tauri::command]
+pub async fn rotate_jpeg_lossless(path: String, degrees: u16) -> Result<(), AppError> {
+  traced("rotate_jpeg_lossless", async move {
+    if !degrees.is_multiple_of(90) {
+      return Err(AppError::InvalidArgument(format!(
+        "lossless rotation must be a multiple of 90 degrees, got {}",
+        degrees
+      )));
+    }
+
+    let path = Path::new(&path);
+    let original = fs::read(path).map_err(|err| AppError::read(path, &err))?;
+    if image::guess_format(&original).ok() != Some(ImageFormat::Jpeg) {
+      return Err(AppError::UnsupportedFormat(format!(
+        "lossless rotation only supports JPEG: {}",
+        path.display()
+      )));
+    }
+
+    let stored = jpeg::exif_orientation(&original)
+      .and_then(Orientation::from_exif)
+      .unwrap_or(Orientation::IDENTITY);
+    let total = stored.then(Orientation::rotation((degrees / 90 % 4) as u8));
+
+    let mut rotated = reorient(path, &original, total)?;
+    jpeg::set_exif_orientation(&mut rotated, 1);
+
+    // Write alongside and rename so a failure never leaves a truncated file.
+    let temp = path.with_file_name(format!(
+      ".{}.tmp",
+      path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    fs::write(&temp, &*rotated)
+      .and_then(|()| fs::rename(&temp, path))
+      .map_err(|err| {
+        let _ = fs::remove_file(&temp);
+        AppError::write(path, &err)
+      })
+  })
+  .await
+}
+
+/// Applies `orientation` to the JPEG read from `path` in the DCT domain,
+/// trimming the partial MCU blocks on the edges that can't be moved. Its
+/// markers, EXIF included, are copied unchanged.
+pub(crate) fn reorient(
+  path: &Path,
+  jpeg: &[u8],
+  orientation: Orientation,
+) -> Result<OwnedBuf, AppError> {
+  let mut transform = Transform::op(transform_op(orientation));
+  transform.trim = true;
+  turbojpeg::transform(&transform, jpeg)
+    .map_err(|err| AppError::DecodeFailed(format!("failed to rotate {}: {}", path.display(), err)))
+}
+
+fn transform_op(orientation: Orientation) -> TransformOp {
+  match (orientation.is_flipped(), orientation.quarter_turns()) {
+    (false, 0) => TransformOp::None,
+    (false, 1) => TransformOp::Rot90,
+    (false, 2) => TransformOp::Rot180,
+    (false, _) => TransformOp::Rot270,
+    (true, 0) => TransformOp::Hflip,
+    (true, 1) => TransformOp::Transverse,
+    (true, 2) => TransformOp::Vflip,
+    (true, _) => TransformOp::Transpose,
+  }
+}
@@ -0,0 +1,332 @@
+//! Reading and removing image metadata.
+
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+
+use exif::{Exif, In, Tag, Value};
+use image::ImageFormat;
+use serde::Serialize;
+
+use super::file::create_parent_dir;
+use super::lossless::reorient;
+use crate::error::AppError;
+use crate::logging::traced;
+use crate::orientation::Orientation;
+use crate::{heif, jpeg, png, riff};
+
+/// JPEG segments that carry metadata: APP1 (EXIF, XMP), APP2 (ICC) and
+/// APP13 (Photoshop/IPTC).
+const JPEG_METADATA_MARKERS: [u8; 3] = [0xE1, 0xE2, 0xED];
+
+/// PNG chunks that carry metadata: EXIF, ICC and text, which holds XMP.
+const PNG_METADATA_CHUNKS: [&[u8; 4]; 5] = [b"eXIf", b"iCCP", b"iTXt", b"tEXt", b"zTXt"];
+
+/// WebP chunks that carry metadata.
+const WEBP_METADATA_CHUNKS: [&[u8; 4]; 3] = [b"EXIF", b"XMP ", b"ICCP"];
+
+/// Commonly displayed EXIF fields. Anything the file doesn't record is
+/// `None`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExifData {
+  pub camera_make: Option<String>,
+  pub camera_model: Option<String>,
+  pub lens: Option<String>,
+  pub iso: Option<u32>,
+  /// F-number, e.g. `2.8` for f/2.8.
+  pub aperture: Option<f64>,
+  /// Exposure time formatted for display, e.g. "1/250" or "2.5".
+  pub shutter_speed: Option<String>,
+  /// Focal length in millimetres.
+  pub focal_length: Option<f64>,
+  /// Capture time as recorded, "YYYY:MM:DD HH:MM:SS".
+  pub datetime: Option<String>,
+  /// Latitude in decimal degrees, negative south of the equator.
+  pub gps_lat: Option<f64>,
+  /// Longitude in decimal degrees, negative west of Greenwich.
+  pub gps_lon: Option<f64>,
+  pub orientation: Option<u16>,
+}
+
+/// Reads the EXIF block of a JPEG, TIFF, PNG, WebP or HEIF file.
+///
+/// A file with no EXIF data at all yields an `ExifData` with every field
+/// empty rather than an error.
+#[tauri::command]
+pub async fn read_exif(path: String) -> Result<ExifData, AppError> {
+  traced("read_exif", async move {
+    let path = Path::new(&path);
+    let file = File::open(path).map_err(|err| AppError::read(path, &err))?;
+
+    let exif = match exif::Reader::new().read_from_container(&mut BufReader::new(file)) {
+      Ok(exif) => exif,
+      Err(exif::Error::NotFound(_)) => return Ok(ExifData::default()),
+      Err(exif::Error::Io(err)) => return Err(AppError::read(path, &err)),
+      Err(err) => {
+        return Err(AppError::DecodeFailed(format!(
+          "failed to read EXIF from {}: {}",
+          path.display(),
+          err
+        )))
+      }
+    };
+
+    Ok(ExifData {
+      camera_make: ascii(&exif, Tag::Make),
+      camera_model: ascii(&exif, Tag::Model),
+      lens: ascii(&exif, Tag::LensModel),
+      iso: exif
+        .get_field(Tag::PhotographicSensitivity, In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0)),
+      aperture: rational(&exif, Tag::FNumber),
+      shutter_speed: shutter_speed(&exif),
+      focal_length: rational(&exif, Tag::FocalLength),
+      datetime: ascii(&exif, Tag::DateTimeOriginal).or_else(|| ascii(&exif, Tag::DateTime)),
+      gps_lat: gps_coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S'),
+      gps_lon: gps_coordinate(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W'),
+      orientation: exif
+        .get_field(Tag::Orientation, In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        .and_then(|value| u16::try_from(value).ok()),
+    })
+  })
+  .await
+}
+
+/// The EXIF orientation of the file at `path`, treating a missing or
+/// unreadable tag as upright.
+///
+/// HEIF files always count as upright: decoding them already applies the
+/// container's own rotation, which the EXIF tag merely duplicates.
+pub(crate) fn read_orientation(path: &Path) -> Orientation {
+  if heif::is_heif(path) {
+    return Orientation::IDENTITY;
+  }
+  File::open(path)
+    .ok()
+    .and_then(|file| {
+      exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()
+    })
+    .and_then(|exif| {
+      exif
+        .get_field(Tag::Orientation, In::PRIMARY)?
+        .value
+        .get_uint(0)
+    })
+    .and_then(|tag| Orientation::from_exif(u16::try_from(tag).ok()?))
+    .unwrap_or(Orientation::IDENTITY)
+}
+
+fn ascii(exif: &Exif, tag: Tag) -> Option<String> {
+  match &exif.get_field(tag, In::PRIMARY)?.value {
+    Value::Ascii(values) => {
+      let text = String::from_utf8_lossy(values.first()?).trim().to_string();
+      (!text.is_empty()).then_some(text)
+    }
+    _ => None,
+  }
+}
+
+fn rational(exif: &Exif, tag: Tag) -> Option<f64> {
+  match &exif.get_field(tag, In::PRIMARY)?.value {
+    Value::Rational(values) => values.first().filter(|r| r.denom != 0).map(|r| r.to_f64()),
+    _ => None,
+  }
+}
+
+fn shutter_speed(exif: &Exif) -> Option<String> {
+  let seconds = rational(exif, Tag::ExposureTime)?;
+  if seconds > 0.0 && seconds < 1.0 {
+    Some(format!("1/{}", (1.0 / seconds).round()))
+  } else {
+    Some(format!("{}", seconds))
+  }
+}
+
+/// Converts a degrees/minutes/seconds triple to signed decimal degrees.
+/// `negative_ref` is the hemisphere letter ('S' or 'W') that flips the sign.
+fn gps_coordinate(exif: &Exif, tag: Tag, ref_tag: Tag, negative_ref: u8) -> Option<f64> {
+  let Value::Rational(dms) = &exif.get_field(tag, In::PRIMARY)?.value else {
+    return None;
+  };
+  if dms.len() < 3 || dms.iter().take(3).any(|r| r.denom == 0) {
+    return None;
+  }
+  let degrees = dms[0].to_f64() + dms[1].to_f64() / 60.0 + dms[2].to_f64() / 3600.0;
+
+  let negative = match &exif
+    .get_field(ref_tag, In::PRIMARY)
+    .map(|field| &field.value)
+  {
+    Some(Value::Ascii(values)) => values
+      .first()
+      .and_then(|value| value.first())
+      .is_some_and(|letter| letter.eq_ignore_ascii_case(&negative_ref)),
+    _ => false,
+  };
+
+  Some(if negative { -degrees } else { degrees })
+}
+
+/// Writes a copy of `input` to `output` with EXIF, XMP and ICC data
+/// removed, returning how many bytes smaller the copy is.
+///
+/// Only the metadata is dropped: the compressed image data is copied as it
+/// is, so nothing is lost to re-encoding. A JPEG's EXIF orientation is
+/// applied with a lossless rotation first so it still displays upright,
+/// which can rarely make the copy bigger and the saving negative. JPEG,
+/// PNG and WebP are supported; other formats are refused rather than saved
+/// with their metadata intact.
+#[tauri::command]
+pub async fn strip_metadata(input: String, output: String) -> Result<i64, AppError> {
+  traced("strip_metadata", async move {
+    let input = Path::new(&input);
+    let output = Path::new(&output);
+    let original = fs::read(input).map_err(|err| AppError::read(input, &err))?;
+    let stripped = without_metadata(input, &original)?;
+
+    create_parent_dir(output)?;
+    fs::write(output, &stripped).map_err(|err| AppError::write(output, &err))?;
+    Ok(original.len() as i64 - stripped.len() as i64)
+  })
+  .await
+}
+
+/// Copies the contents of `path` without their metadata, picking the
+/// format from the extension.
+fn without_metadata(path: &Path, data: &[u8]) -> Result<Vec<u8>, AppError> {
+  let (stripped, format) = match ImageFormat::from_path(path) {
+    Ok(ImageFormat::Jpeg) => {
+      let orientation = jpeg::exif_orientation(data)
+        .and_then(Orientation::from_exif)
+        .unwrap_or(Orientation::IDENTITY);
+      let stripped = if orientation == Orientation::IDENTITY {
+        jpeg::without_segments(data, &JPEG_METADATA_MARKERS)
+      } else {
+        jpeg::without_segments(&reorient(path, data, orientation)?, &JPEG_METADATA_MARKERS)
+      };
+      (stripped, "JPEG")
+    }
+    Ok(ImageFormat::Png) => (png::without_chunks(data, &PNG_METADATA_CHUNKS), "PNG"),
+    Ok(ImageFormat::WebP) => (
+      riff::webp_without_chunks(data, &WEBP_METADATA_CHUNKS),
+      "WebP",
+    ),
+    _ => {
+      return Err(AppError::UnsupportedFormat(format!(
+        "can't strip metadata from {}: only JPEG, PNG and WebP are supported",
+        path.display()
+      )))
+    }
+  };
+  stripped.ok_or_else(|| {
+    AppError::DecodeFailed(format!(
+      "failed to decode {}: malformed {}",
+      path.display(),
+      format
+    ))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use image::codecs::jpeg::JpegEncoder;
+  use image::codecs::png::PngEncoder;
+  use image::codecs::webp::WebPEncoder;
+  use image::{ExtendedColorType, GenericImageView, ImageEncoder, Rgb, RgbImage, Rgba, RgbaImage};
+
+  // A big-endian TIFF header with no entries, enough to be embedded.
+  const EXIF: &[u8] = b"MM\x00\x2a\x00\x00\x00\x08\x00\x00\x00\x00\x00\x00";
+  const ICC: &[u8] = b"not a real profile, but embedded all the same";
+
+  fn pixels() -> RgbaImage {
+    RgbaImage::from_fn(8, 6, |x, y| Rgba([x as u8 * 30, y as u8 * 40, 90, 200]))
+  }
+
+  fn contains(data: &[u8], needle: &[u8]) -> bool {
+    data.windows(needle.len()).any(|window| window == needle)
+  }
+
+  #[test]
+  fn png_loses_its_metadata_and_keeps_its_pixels() {
+    let image = pixels();
+    let mut original = Vec::new();
+    let mut encoder = PngEncoder::new(&mut original);
+    encoder.set_exif_metadata(EXIF.to_vec()).unwrap();
+    encoder.set_icc_profile(ICC.to_vec()).unwrap();
+    encoder
+      .write_image(image.as_raw(), 8, 6, ExtendedColorType::Rgba8)
+      .unwrap();
+    assert!(contains(&original, b"eXIf") && contains(&original, b"iCCP"));
+
+    let stripped = without_metadata(Path::new("photo.png"), &original).unwrap();
+
+    assert!(!contains(&stripped, b"eXIf") && !contains(&stripped, b"iCCP"));
+    let decoded = image::load_from_memory_with_format(&stripped, ImageFormat::Png).unwrap();
+    assert_eq!(decoded.to_rgba8(), image);
+  }
+
+  #[test]
+  fn webp_loses_its_metadata_and_keeps_its_pixels() {
+    let image = pixels();
+    let mut original = Vec::new();
+    let mut encoder = WebPEncoder::new_lossless(&mut original);
+    encoder.set_exif_metadata(EXIF.to_vec()).unwrap();
+    encoder.set_icc_profile(ICC.to_vec()).unwrap();
+    encoder
+      .write_image(image.as_raw(), 8, 6, ExtendedColorType::Rgba8)
+      .unwrap();
+    assert!(contains(&original, b"EXIF") && contains(&original, b"ICCP"));
+
+    let stripped = without_metadata(Path::new("photo.webp"), &original).unwrap();
+
+    assert!(!contains(&stripped, b"EXIF") && !contains(&stripped, b"ICCP"));
+    let riff_size = u32::from_le_bytes(stripped[4..8].try_into().unwrap()) as usize;
+    assert_eq!(riff_size, stripped.len() - 8);
+    // The VP8X flags no longer announce ICC or EXIF data.
+    assert_eq!(&stripped[12..16], b"VP8X");
+    assert_eq!(stripped[20] & 0x28, 0);
+    let decoded = image::load_from_memory_with_format(&stripped, ImageFormat::WebP).unwrap();
+    assert_eq!(decoded.to_rgba8(), image);
+  }
+
+  #[test]
+  fn jpeg_is_turned_upright_before_its_exif_goes() {
+    // Sized in whole MCUs so the rotation has nothing to trim.
+    let image = RgbImage::from_pixel(32, 16, Rgb([200, 100, 50]));
+    let mut encoded = Vec::new();
+    JpegEncoder::new(&mut encoded)
+      .write_image(image.as_raw(), 32, 16, ExtendedColorType::Rgb8)
+      .unwrap();
+    // An EXIF block whose only entry is orientation 6, a quarter turn.
+    let tiff: &[u8] = &[
+      b'M', b'M', 0x00, 0x2A, 0x00, 0x00, 0x00, 0x08, // header
+      0x00, 0x01, // one entry
+      0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x06, 0x00, 0x00, // orientation
+      0x00, 0x00, 0x00, 0x00, // no next IFD
+    ];
+    let mut original = encoded[..2].to_vec();
+    original.extend_from_slice(&[0xFF, 0xE1]);
+    original.extend_from_slice(&(2 + 6 + tiff.len() as u16).to_be_bytes());
+    original.extend_from_slice(b"Exif\0\0");
+    original.extend_from_slice(tiff);
+    original.extend_from_slice(&encoded[2..]);
+    assert_eq!(jpeg::exif_orientation(&original), Some(6));
+
+    let stripped = without_metadata(Path::new("photo.jpg"), &original).unwrap();
+
+    assert!(!contains(&stripped, b"Exif"));
+    let decoded = image::load_from_memory_with_format(&stripped, ImageFormat::Jpeg).unwrap();
+    assert_eq!(decoded.dimensions(), (16, 32));
+  }
+
+  #[test]
+  fn other_formats_are_refused() {
+    let err = without_metadata(Path::new("scan.tiff"), &[]).unwrap_err();
+    assert!(matches!(err, AppError::UnsupportedFormat(_)));
+  }
+}
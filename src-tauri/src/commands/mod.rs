@@ -0,0 +1,89 @@
+//! Commands exposed to the frontend through `invoke`.
+//!
+//! Editing commands take the image either as a raw RGBA buffer (`data`,
+//! `width` and `height`) or as the `doc_id` of an open document. Given a
+//! document, they edit its buffer in place, record the edit for undo and
+//! return the document's details instead of pixels.
+
+mod adjust;
+mod analysis;
+mod batch;
+mod canvas;
+mod capture;
+mod clipboard;
+mod color;
+mod compose;
+mod documents;
+mod export;
+mod file;
+mod history;
+mod logs;
+mod lossless;
+mod metadata;
+mod palette;
+mod preview;
+mod svg;
+mod thumbnail;
+mod tiles;
+mod transform;
+mod update;
+
+pub use adjust::*;
+pub use analysis::*;
+pub use batch::*;
+pub use canvas::*;
+pub use capture::*;
+pub use clipboard::*;
+pub use color::*;
+pub use compose::*;
+pub use documents::*;
+pub use export::*;
+pub use file::*;
+pub use history::*;
+pub use logs::*;
+pub use lossless::*;
+pub use metadata::*;
+pub use palette::*;
+pub use preview::*;
+pub use svg::*;
+pub use thumbnail::*;
+pub use tiles::*;
+pub use transform::*;
+pub use update::*;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use image::RgbaImage;
+use serde::Serialize;
+
+use crate::error::AppError;
+
+/// A decoded image handed back to the frontend as raw RGBA.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageData {
+  pub width: u32,
+  pub height: u32,
+  /// Base64-encoded RGBA8 pixels, row-major, no padding between rows.
+  pub data: String,
+}
+
+impl ImageData {
+  pub fn from_rgba(image: &RgbaImage) -> Self {
+    Self {
+      width: image.width(),
+      height: image.height(),
+      data: STANDARD.encode(image.as_raw()),
+    }
+  }
+}
+
+/// Wraps a raw RGBA buffer from the frontend, checking it matches the
+/// stated dimensions.
+pub(crate) fn rgba_from_raw(data: Vec<u8>, width: u32, height: u32) -> Result<RgbaImage, AppError> {
+  let len = data.len();
+  RgbaImage::from_raw(width, height, data).ok_or_else(|| {
+    AppError::InvalidArgument(format!(
+      "buffer of {} bytes does not match a {}x{} RGBA image",
+      len, width, height
+    ))
+  })
+}
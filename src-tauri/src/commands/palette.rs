@@ -0,0 +1,331 @@
+//! Reducing images to fewer colours.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::State;
+
+use super::{edit_source, finish_edit, rgba_from_raw, Edited};
+use crate::documents::{DocumentId, Documents};
+use crate::error::AppError;
+use crate::logging::traced;
+
+/// A colour and the number of pixels that have it.
+type ColorCount = ([u8; 4], u64);
+
+/// `extract_palette` clusters a sample of at most this many pixels.
+const PALETTE_SAMPLE_PIXELS: u64 = 100_000;
+/// Upper bound on k-means iterations; it usually settles well before.
+const KMEANS_MAX_ITERATIONS: usize = 20;
+
+/// An image reduced to a palette, and the palette itself.
+#[derive(Debug, Clone, Serialize)]
+pub struct QuantizedImage {
+  #[serde(flatten)]
+  pub image: Edited,
+  /// RGBA colours, most used first. Every pixel of `image` is one of them.
+  pub palette: Vec<[u8; 4]>,
+}
+
+/// Reduces each colour channel to `levels` evenly spaced values, from 0 to
+/// 255 inclusive. Alpha is left untouched.
+#[tauri::command]
+pub async fn posterize(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  levels: u8,
+) -> Result<Edited, AppError> {
+  traced("posterize", async move {
+    if levels < 2 {
+      return Err(AppError::InvalidArgument(format!(
+        "posterize needs at least 2 levels, got {}",
+        levels
+      )));
+    }
+
+    let steps = u32::from(levels - 1);
+    let lut: Vec<u8> = (0..=255u32)
+      .map(|value| {
+        let step = (value * steps + 127) / 255;
+        ((step * 255 + steps / 2) / steps) as u8
+      })
+      .collect();
+
+    let mut image = edit_source(&documents, doc_id, data, width, height)?;
+    for pixel in image.pixels_mut() {
+      for channel in &mut pixel.0[..3] {
+        *channel = lut[usize::from(*channel)];
+      }
+    }
+    finish_edit(&documents, doc_id, image)
+  })
+  .await
+}
+
+/// Reduces an RGBA buffer to at most `max_colors` colours with median cut,
+/// returning the reduced image and its palette.
+///
+/// Alpha takes part like any other channel, except that fully transparent
+/// pixels all count as one colour, `[0, 0, 0, 0]`. An image that already
+/// has few enough colours comes back unchanged.
+#[tauri::command]
+pub async fn quantize(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  max_colors: u16,
+) -> Result<QuantizedImage, AppError> {
+  traced("quantize", async move {
+    if max_colors == 0 {
+      return Err(AppError::InvalidArgument(
+        "a palette needs at least 1 colour".into(),
+      ));
+    }
+
+    let mut image = edit_source(&documents, doc_id, data, width, height)?;
+    let normalise = |pixel: [u8; 4]| if pixel[3] == 0 { [0; 4] } else { pixel };
+
+    let mut counts: HashMap<[u8; 4], u64> = HashMap::new();
+    for pixel in image.pixels() {
+      *counts.entry(normalise(pixel.0)).or_default() += 1;
+    }
+    let boxes = median_cut(counts.into_iter().collect(), usize::from(max_colors));
+
+    let mut lookup = HashMap::new();
+    let mut palette = Vec::with_capacity(boxes.len());
+    for colors in &boxes {
+      let (color, population) = mean(colors);
+      for (member, _) in colors {
+        lookup.insert(*member, color);
+      }
+      palette.push((color, population));
+    }
+    palette.sort_by_key(|&(_, population)| Reverse(population));
+    for pixel in image.pixels_mut() {
+      pixel.0 = lookup[&normalise(pixel.0)];
+    }
+
+    Ok(QuantizedImage {
+      image: finish_edit(&documents, doc_id, image)?,
+      palette: palette.into_iter().map(|(color, _)| color).collect(),
+    })
+  })
+  .await
+}
+
+/// Finds the `count` most dominant colours of an RGBA buffer with k-means
+/// in RGB space, most prominent first.
+///
+/// Big images are sampled down to about 100,000 pixels first. Mostly
+/// transparent pixels are ignored, and the colours come back opaque. Fewer
+/// than `count` colours are returned if the image doesn't have that many.
+#[tauri::command]
+pub async fn extract_palette(
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  count: usize,
+) -> Result<Vec<[u8; 4]>, AppError> {
+  traced("extract_palette", async move {
+    if count == 0 {
+      return Err(AppError::InvalidArgument(
+        "a palette needs at least 1 colour".into(),
+      ));
+    }
+
+    let image = rgba_from_raw(data, width, height)?;
+    let pixels = u64::from(width) * u64::from(height);
+    let step = (pixels as f64 / PALETTE_SAMPLE_PIXELS as f64)
+      .sqrt()
+      .ceil()
+      .max(1.0) as usize;
+
+    let mut counts: HashMap<[u8; 4], u64> = HashMap::new();
+    for row in image.rows().step_by(step) {
+      for pixel in row.step_by(step).filter(|pixel| pixel[3] >= 128) {
+        *counts
+          .entry([pixel[0], pixel[1], pixel[2], 255])
+          .or_default() += 1;
+      }
+    }
+    let colors: Vec<ColorCount> = counts.into_iter().collect();
+
+    // Seeding from median cut keeps the result deterministic and starts
+    // k-means close to where it ends up.
+    let mut centroids: Vec<[f32; 3]> = median_cut(colors.clone(), count)
+      .iter()
+      .map(|group| {
+        let (mean, _) = mean(group);
+        [mean[0], mean[1], mean[2]].map(f32::from)
+      })
+      .collect();
+    let mut populations = vec![0; centroids.len()];
+    for _ in 0..KMEANS_MAX_ITERATIONS {
+      let mut sums = vec![[0f64; 3]; centroids.len()];
+      populations.fill(0);
+      for (color, weight) in &colors {
+        let nearest = nearest(&centroids, color);
+        for (sum, &value) in sums[nearest].iter_mut().zip(color) {
+          *sum += f64::from(value) * *weight as f64;
+        }
+        populations[nearest] += weight;
+      }
+
+      let mut moved = false;
+      for ((centroid, sum), &population) in centroids.iter_mut().zip(&sums).zip(&populations) {
+        if population == 0 {
+          continue;
+        }
+        let updated = sum.map(|sum| (sum / population as f64) as f32);
+        moved |= centroid
+          .iter()
+          .zip(&updated)
+          .any(|(old, new)| (old - new).abs() > 0.5);
+        *centroid = updated;
+      }
+      if !moved {
+        break;
+      }
+    }
+
+    let mut palette: Vec<ColorCount> = centroids
+      .iter()
+      .zip(populations)
+      .filter(|&(_, population)| population > 0)
+      .map(|(centroid, population)| {
+        let [r, g, b] = centroid.map(|value| value.round() as u8);
+        ([r, g, b, 255], population)
+      })
+      .collect();
+    palette.sort_by_key(|&(_, population)| Reverse(population));
+    Ok(palette.into_iter().map(|(color, _)| color).collect())
+  })
+  .await
+}
+
+/// The mean colour of an RGBA buffer.
+///
+/// Colours are weighted by alpha, so transparent pixels don't pull the
+/// result towards whatever colour they happen to hold. The alpha of the
+/// result is the mean alpha.
+#[tauri::command]
+pub async fn average_color(data: Vec<u8>, width: u32, height: u32) -> Result<[u8; 4], AppError> {
+  traced("average_color", async move {
+    let pixels = u64::from(width) * u64::from(height);
+    if pixels == 0 {
+      return Err(AppError::InvalidArgument(
+        "an empty image has no average colour".into(),
+      ));
+    }
+
+    let image = rgba_from_raw(data, width, height)?;
+    let mut sums = [0u64; 3];
+    let mut alpha = 0u64;
+    for pixel in image.pixels() {
+      let weight = u64::from(pixel[3]);
+      for (sum, &value) in sums.iter_mut().zip(&pixel.0[..3]) {
+        *sum += u64::from(value) * weight;
+      }
+      alpha += weight;
+    }
+
+    if alpha == 0 {
+      return Ok([0; 4]);
+    }
+    let [r, g, b] = sums.map(|sum| ((sum + alpha / 2) / alpha) as u8);
+    Ok([r, g, b, ((alpha + pixels / 2) / pixels) as u8])
+  })
+  .await
+}
+
+/// The index of the centroid closest to `color`.
+fn nearest(centroids: &[[f32; 3]], color: &[u8; 4]) -> usize {
+  let distance = |centroid: &[f32; 3]| -> f32 {
+    centroid
+      .iter()
+      .zip(color)
+      .map(|(c, &v)| (c - f32::from(v)).powi(2))
+      .sum()
+  };
+  (0..centroids.len())
+    .min_by(|&a, &b| distance(&centroids[a]).total_cmp(&distance(&centroids[b])))
+    .unwrap_or(0)
+}
+
+/// Splits `colors` into at most `max_boxes` groups, each time halving the
+/// group with the widest channel range at the weighted median of that
+/// channel.
+fn median_cut(colors: Vec<ColorCount>, max_boxes: usize) -> Vec<Vec<ColorCount>> {
+  if colors.is_empty() {
+    return Vec::new();
+  }
+  let mut boxes = vec![colors];
+  while boxes.len() < max_boxes {
+    let Some((index, channel)) = boxes
+      .iter()
+      .enumerate()
+      .filter(|(_, colors)| colors.len() > 1)
+      .map(|(index, colors)| {
+        let (channel, range) = widest_channel(colors);
+        (index, channel, range)
+      })
+      .max_by_key(|&(_, _, range)| range)
+      .map(|(index, channel, _)| (index, channel))
+    else {
+      break;
+    };
+
+    let mut colors = boxes.swap_remove(index);
+    colors.sort_unstable_by_key(|(color, _)| color[channel]);
+    let total: u64 = colors.iter().map(|(_, count)| count).sum();
+    let mut seen = 0;
+    let median = colors
+      .iter()
+      .position(|(_, count)| {
+        seen += count;
+        seen * 2 >= total
+      })
+      .unwrap_or(0);
+    // Both halves must keep at least one colour.
+    let split = (median + 1).min(colors.len() - 1);
+    boxes.push(colors.split_off(split));
+    boxes.push(colors);
+  }
+  boxes
+}
+
+/// The channel whose values spread widest across `colors`, and that spread.
+fn widest_channel(colors: &[ColorCount]) -> (usize, u8) {
+  (0..4)
+    .map(|channel| {
+      let (min, max) = colors
+        .iter()
+        .fold((u8::MAX, u8::MIN), |(min, max), (color, _)| {
+          (min.min(color[channel]), max.max(color[channel]))
+        });
+      (channel, max - min)
+    })
+    .max_by_key(|&(_, range)| range)
+    .unwrap_or((0, 0))
+}
+
+/// The pixel-weighted average colour of `colors`, and how many pixels it
+/// stands for.
+fn mean(colors: &[ColorCount]) -> ColorCount {
+  let mut sums = [0u64; 4];
+  let mut total = 0;
+  for (color, count) in colors {
+    for (sum, &value) in sums.iter_mut().zip(color) {
+      *sum += u64::from(value) * count;
+    }
+    total += count;
+  }
+  (sums.map(|sum| ((sum + total / 2) / total) as u8), total)
+}
@@ -0,0 +1,94 @@
+//! Registering images with the `imgpro://` preview store.
+//!
+//! Previews are made on the Rust side, from files here and from open
+//! documents by `document_image`, so their pixels never cross the IPC
+//! bridge.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use image::{ImageFormat, RgbaImage};
+use serde::Serialize;
+use tauri::State;
+
+use super::file::decode_file;
+use crate::error::AppError;
+use crate::logging::{traced, traced_sync};
+use crate::png;
+use crate::preview::{self, PreviewStore};
+use crate::sync::lock;
+
+/// A stored preview and the URL an `<img>` can load it from.
+#[derive(Debug, Clone, Serialize)]
+pub struct Preview {
+  pub id: u64,
+  pub url: String,
+}
+
+/// Stores the image at `path` as a preview.
+///
+/// PNG, JPEG and WebP files are served as they are; anything else is
+/// decoded and re-encoded as PNG. Pass the id of the preview this one
+/// supersedes as `replaces` and it is released straight away rather than
+/// waiting to be evicted.
+#[tauri::command]
+pub async fn preview_file(
+  store: State<'_, Mutex<PreviewStore>>,
+  path: String,
+  replaces: Option<u64>,
+) -> Result<Preview, AppError> {
+  traced("preview_file", async move {
+    let path = Path::new(&path);
+    let bytes = fs::read(path).map_err(|err| AppError::read(path, &err))?;
+
+    let (mime, data) = match image::guess_format(&bytes) {
+      Ok(ImageFormat::Png) => ("image/png", bytes),
+      Ok(ImageFormat::Jpeg) => ("image/jpeg", bytes),
+      Ok(ImageFormat::WebP) => ("image/webp", bytes),
+      _ => ("image/png", encode_png(&decode_file(path)?.into_rgba8())?),
+    };
+    Ok(insert(&store, mime, data, replaces))
+  })
+  .await
+}
+
+/// Drops a preview the frontend no longer shows. Returns whether it was
+/// still stored.
+#[tauri::command]
+pub fn release_preview(store: State<'_, Mutex<PreviewStore>>, id: u64) -> Result<bool, AppError> {
+  traced_sync("release_preview", || lock(&store).remove(id))
+}
+
+/// Stores `image` as a PNG preview, released early like `preview_file`'s
+/// `replaces`. The encoding happens before the store is locked.
+pub(crate) fn store_image(
+  store: &Mutex<PreviewStore>,
+  image: &RgbaImage,
+  replaces: Option<u64>,
+) -> Result<Preview, AppError> {
+  let data = encode_png(image)?;
+  Ok(insert(store, "image/png", data, replaces))
+}
+
+fn insert(
+  store: &Mutex<PreviewStore>,
+  mime: &'static str,
+  data: Vec<u8>,
+  replaces: Option<u64>,
+) -> Preview {
+  let mut store = lock(store);
+  if let Some(old) = replaces {
+    store.remove(old);
+  }
+  let id = store.insert(mime, data);
+  Preview {
+    id,
+    url: preview::url(id),
+  }
+}
+
+fn encode_png(image: &RgbaImage) -> Result<Vec<u8>, AppError> {
+  png::encode_fast(image)
+    .map_err(|err| AppError::EncodeFailed(format!("failed to encode preview: {}", err)))
+}
@@ -0,0 +1,115 @@
+//! Rendering SVG documents to pixels.
+
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{fontdb, Options, Tree};
+
+use super::file::{check_pixels, max_pixels};
+use super::{rgba_from_raw, ImageData};
+use crate::error::AppError;
+use crate::logging::traced;
+
+/// System fonts, loaded on first use. Scanning them takes long enough that
+/// it shouldn't happen for every render.
+fn system_fonts() -> Arc<fontdb::Database> {
+  static FONTS: OnceLock<Arc<fontdb::Database>> = OnceLock::new();
+  FONTS
+    .get_or_init(|| {
+      let mut fonts = fontdb::Database::new();
+      fonts.load_system_fonts();
+      Arc::new(fonts)
+    })
+    .clone()
+}
+
+/// Renders the SVG (or gzipped SVGZ) at `path` to RGBA.
+///
+/// With neither `width` nor `height`, the output is the SVG's own size
+/// times `scale` (default 1), so 2 and 3 give @2x and @3x assets. Given
+/// both, the drawing is scaled to fit inside that box keeping its aspect
+/// ratio; given one, the other follows from the aspect ratio. `scale` is
+/// ignored whenever a dimension is given. Sizes over the pixel budget from
+/// `set_max_pixels` fail with `TooLarge` before anything is drawn.
+///
+/// Text is drawn with the system fonts. When none of the families in a
+/// `font-family` are installed, the system's default serif font is used
+/// instead, and characters that font lacks are taken from any installed
+/// font that has them. Text is left out if no fonts can be found at all.
+#[tauri::command]
+pub async fn rasterize_svg(
+  path: String,
+  width: Option<u32>,
+  height: Option<u32>,
+  scale: Option<f32>,
+) -> Result<ImageData, AppError> {
+  traced("rasterize_svg", async move {
+    if width == Some(0) || height == Some(0) {
+      return Err(AppError::InvalidArgument(
+        "output size must be at least 1 pixel".into(),
+      ));
+    }
+
+    let path = Path::new(&path);
+    let source = fs::read(path).map_err(|err| AppError::read(path, &err))?;
+
+    let options = Options {
+      // Relative `href`s to images resolve against the SVG's folder.
+      resources_dir: path.parent().map(Path::to_path_buf),
+      fontdb: system_fonts(),
+      ..Options::default()
+    };
+    let tree = Tree::from_data(&source, &options).map_err(|err| {
+      AppError::DecodeFailed(format!("failed to parse {}: {}", path.display(), err))
+    })?;
+
+    let size = tree.size();
+    let (intrinsic_w, intrinsic_h) = (size.width(), size.height());
+    let factor = match (width, height) {
+      (None, None) => {
+        let scale = scale.unwrap_or(1.0);
+        if !(scale > 0.0 && scale.is_finite()) {
+          return Err(AppError::InvalidArgument(format!(
+            "scale must be positive, got {}",
+            scale
+          )));
+        }
+        scale
+      }
+      (Some(w), None) => w as f32 / intrinsic_w,
+      (None, Some(h)) => h as f32 / intrinsic_h,
+      (Some(w), Some(h)) => (w as f32 / intrinsic_w).min(h as f32 / intrinsic_h),
+    };
+
+    let out_w = (intrinsic_w * factor).round().max(1.0) as u32;
+    let out_h = (intrinsic_h * factor).round().max(1.0) as u32;
+    check_pixels(
+      format_args!("the rendered {}", path.display()),
+      out_w,
+      out_h,
+      max_pixels(),
+    )?;
+    let mut pixmap = Pixmap::new(out_w, out_h).ok_or_else(|| {
+      AppError::InvalidArgument(format!("invalid output size {}x{}", out_w, out_h))
+    })?;
+    resvg::render(
+      &tree,
+      Transform::from_scale(out_w as f32 / intrinsic_w, out_h as f32 / intrinsic_h),
+      &mut pixmap.as_mut(),
+    );
+
+    // tiny-skia works in premultiplied alpha; the rest of the app doesn't.
+    let pixels = pixmap
+      .pixels()
+      .iter()
+      .flat_map(|pixel| {
+        let color = pixel.demultiply();
+        [color.red(), color.green(), color.blue(), color.alpha()]
+      })
+      .collect();
+    Ok(ImageData::from_rgba(&rgba_from_raw(pixels, out_w, out_h)?))
+  })
+  .await
+}
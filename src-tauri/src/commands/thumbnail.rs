@@ -0,0 +1,74 @@
+//! Small previews for file browsers.
+
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::RgbaImage;
+use tauri::State;
+
+use super::file::decode_file;
+use super::metadata::read_orientation;
+use super::ImageData;
+use crate::error::AppError;
+use crate::logging::traced;
+use crate::thumbnail_cache::ThumbnailCache;
+
+/// Decodes the image at `path` and scales it so its longest edge is
+/// `max_edge` pixels, keeping the aspect ratio.
+///
+/// The EXIF orientation is applied, so portrait photos come back upright.
+/// Results are cached on disk and reused until the source file changes.
+#[tauri::command]
+pub async fn make_thumbnail(
+  cache: State<'_, ThumbnailCache>,
+  path: String,
+  max_edge: u32,
+) -> Result<ImageData, AppError> {
+  traced("make_thumbnail", async move {
+    if max_edge == 0 {
+      return Err(AppError::InvalidArgument(
+        "thumbnail size must be at least 1 pixel".into(),
+      ));
+    }
+
+    let path = Path::new(&path);
+    let key = cache.key(path, max_edge);
+    if let Some(cached) = key.as_ref().and_then(|key| cache.get(key)) {
+      return Ok(ImageData::from_rgba(&cached));
+    }
+
+    let thumbnail = render_thumbnail(path, max_edge)?;
+    if let Some(key) = &key {
+      if let Err(err) = cache.put(key, &thumbnail) {
+        tracing::warn!("failed to cache thumbnail: {}", err);
+      }
+    }
+    Ok(ImageData::from_rgba(&thumbnail))
+  })
+  .await
+}
+
+/// Empties the thumbnail cache, returning the number of bytes freed.
+#[tauri::command]
+pub async fn clear_thumbnail_cache(cache: State<'_, ThumbnailCache>) -> Result<u64, AppError> {
+  traced("clear_thumbnail_cache", async move {
+    cache
+      .clear()
+      .map_err(|err| AppError::Io(format!("failed to clear thumbnail cache: {}", err)))
+  })
+  .await
+}
+
+fn render_thumbnail(path: &Path, max_edge: u32) -> Result<RgbaImage, AppError> {
+  let image = decode_file(path)?;
+
+  let scaled = if image.width().max(image.height()) > max_edge {
+    // Box sampling is much faster than a filtered resize when shrinking a
+    // large photo down to a grid cell.
+    image.thumbnail(max_edge, max_edge)
+  } else {
+    image.resize(max_edge, max_edge, FilterType::Triangle)
+  };
+
+  Ok(read_orientation(path).apply(scaled.to_rgba8()))
+}
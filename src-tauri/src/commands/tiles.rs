@@ -0,0 +1,794 @@
+//! Tiled access to images too big to hand to the frontend whole.
+//!
+//! Level 0 is the image at full size and each level after it halves both
+//! sides, rounding up, down to a single pixel. Tiles are cut from the
+//! stored pixels; the EXIF orientation is not applied.
+//!
+//! Levels are decoded whole and cached while they fit the pixel budget.
+//! Past that, non-interlaced PNGs and TIFFs are read a few rows at a time
+//! and only the requested tile is kept, so even level 0 of a gigapixel
+//! image loads. Other formats can't be read in part, and `image_levels`
+//! lists their oversized levels as unavailable.
+
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use ::png::Transformations;
+use image::{imageops, ImageFormat, Rgba, RgbaImage};
+use serde::Serialize;
+use tauri::State;
+use tiff::decoder::{Decoder as TiffDecoder, DecodingResult};
+use tiff::tags::Tag;
+use tiff::ColorType as TiffColor;
+use turbojpeg::{Decompressor, Image, PixelFormat, ScalingFactor};
+
+use super::file::{
+  check_file_len, check_pixels, decode_file, max_pixels, read_dimensions, sniff_format,
+};
+use super::ImageData;
+use crate::error::AppError;
+use crate::logging::traced;
+use crate::sync::lock;
+use crate::tile_cache::{LevelKey, TileCache};
+
+/// The coarsest JPEG level libjpeg-turbo decodes directly, at 1/8 scale.
+const MAX_JPEG_LEVEL: u32 = 3;
+
+/// The size of one level of an image.
+#[derive(Debug, Clone, Serialize)]
+pub struct TileLevel {
+  pub level: u32,
+  pub width: u32,
+  pub height: u32,
+  /// Whether `load_tile` can serve this level under the pixel budget.
+  pub available: bool,
+}
+
+/// Lists the levels of the image at `path`, from full size down to 1×1,
+/// and which of them `load_tile` can serve under the pixel budget.
+///
+/// Only the headers are read.
+#[tauri::command]
+pub async fn image_levels(path: String) -> Result<Vec<TileLevel>, AppError> {
+  traced("image_levels", async move {
+    let source = TileSource::open(Path::new(&path))?;
+    let budget = max_pixels();
+    Ok(
+      (0..=max_level(source.width, source.height))
+        .map(|level| {
+          let (width, height) = level_size(source.width, source.height, level);
+          TileLevel {
+            level,
+            width,
+            height,
+            available: source.decoding(level, budget).is_some(),
+          }
+        })
+        .collect(),
+    )
+  })
+  .await
+}
+
+/// Returns tile (`x`, `y`) of `level` of the image at `path`, where tiles
+/// are `tile_size` pixels square and counted from the top left. Tiles on
+/// the right and bottom edges are cut short by the edge of the level.
+///
+/// JPEG levels 1 to 3 are decoded at reduced size, and coarser ones are
+/// shrunk from level 3. Levels that fit the pixel budget are cached in
+/// memory, up to 512 MB; tiles of bigger ones are streamed from the file
+/// each time. Levels `image_levels` lists as unavailable fail with
+/// `TooLarge`.
+#[tauri::command]
+pub async fn load_tile(
+  cache: State<'_, Mutex<TileCache>>,
+  path: String,
+  level: u32,
+  x: u32,
+  y: u32,
+  tile_size: u32,
+) -> Result<ImageData, AppError> {
+  traced("load_tile", async move {
+    if tile_size == 0 {
+      return Err(AppError::InvalidArgument(
+        "tiles must be at least 1 pixel".into(),
+      ));
+    }
+
+    let path = Path::new(&path);
+    let source = TileSource::open(path)?;
+    let max_level = max_level(source.width, source.height);
+    if level > max_level {
+      return Err(AppError::InvalidArgument(format!(
+        "level {} is out of range: {} has levels 0 to {}",
+        level,
+        path.display(),
+        max_level
+      )));
+    }
+
+    let (level_width, level_height) = level_size(source.width, source.height, level);
+    let left = u64::from(x) * u64::from(tile_size);
+    let top = u64::from(y) * u64::from(tile_size);
+    if left >= u64::from(level_width) || top >= u64::from(level_height) {
+      return Err(AppError::InvalidArgument(format!(
+        "tile ({}, {}) is outside level {}, which is {}x{}",
+        x, y, level, level_width, level_height
+      )));
+    }
+    let rect = Rect {
+      left: left as u32,
+      top: top as u32,
+      width: tile_size.min(level_width - left as u32),
+      height: tile_size.min(level_height - top as u32),
+    };
+
+    let budget = max_pixels();
+    let tile = match source.decoding(level, budget) {
+      None => return Err(source.unavailable(path, level, budget)),
+      Some(Decoding::Streamed(stream)) if !fits((level_width, level_height), budget) => {
+        check_pixels(
+          format_args!("tile ({}, {})", x, y),
+          rect.width,
+          rect.height,
+          budget,
+        )?;
+        stream.region(path, &source, level, rect)?
+      }
+      Some(decoding) => {
+        let image = level_image(&cache, path, &source, level, decoding)?;
+        imageops::crop_imm(&*image, rect.left, rect.top, rect.width, rect.height).to_image()
+      }
+    };
+    Ok(ImageData::from_rgba(&tile))
+  })
+  .await
+}
+
+/// A rectangle of one level, in that level's pixels.
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+  left: u32,
+  top: u32,
+  width: u32,
+  height: u32,
+}
+
+/// How a level gets decoded.
+#[derive(Debug, Clone, Copy)]
+enum Decoding {
+  /// Straight at the level's scale by libjpeg-turbo.
+  Jpeg,
+  /// Shrunk from the whole image.
+  Whole,
+  /// Averaged from the pixels as they are read.
+  Streamed(Stream),
+}
+
+/// The size and format of an image, which decide how each level is read.
+#[derive(Debug)]
+struct TileSource {
+  width: u32,
+  height: u32,
+  format: ImageFormat,
+  stream: Option<Stream>,
+}
+
+impl TileSource {
+  fn open(path: &Path) -> Result<Self, AppError> {
+    let (width, height) = read_dimensions(path)?;
+    let format = sniff_format(path)?;
+    Ok(Self {
+      width,
+      height,
+      format,
+      stream: Stream::detect(path, format),
+    })
+  }
+
+  /// How `level` can be decoded under the pixel `budget`, or `None` if it
+  /// can't be.
+  fn decoding(&self, level: u32, budget: u64) -> Option<Decoding> {
+    if self.format == ImageFormat::Jpeg && level > 0 && fits(self.jpeg_size(level), budget) {
+      Some(Decoding::Jpeg)
+    } else if fits((self.width, self.height), budget) {
+      Some(Decoding::Whole)
+    } else {
+      self.stream.map(Decoding::Streamed)
+    }
+  }
+
+  /// The size libjpeg-turbo decodes at for `level`.
+  fn jpeg_size(&self, level: u32) -> (u32, u32) {
+    level_size(self.width, self.height, level.min(MAX_JPEG_LEVEL))
+  }
+
+  /// The error for a level that `decoding` has no way to read.
+  fn unavailable(&self, path: &Path, level: u32, budget: u64) -> AppError {
+    let (width, height) = if self.format == ImageFormat::Jpeg && level > 0 {
+      self.jpeg_size(level)
+    } else {
+      (self.width, self.height)
+    };
+    AppError::TooLarge {
+      message: format!(
+        "level {} of {} needs {}x{} decoded, over the {:.1} megapixel limit",
+        level,
+        path.display(),
+        width,
+        height,
+        budget as f64 / 1e6
+      ),
+      required: u64::from(width) * u64::from(height),
+      limit: budget,
+    }
+  }
+}
+
+fn fits((width, height): (u32, u32), budget: u64) -> bool {
+  u64::from(width) * u64::from(height) <= budget
+}
+
+/// The size of `level` of a `width` by `height` image.
+fn level_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+  let divisor = 1u64 << level.min(32);
+  let scale = |side: u32| u64::from(side).div_ceil(divisor).max(1) as u32;
+  (scale(width), scale(height))
+}
+
+/// The coarsest level of a `width` by `height` image, the first at 1×1.
+fn max_level(width: u32, height: u32) -> u32 {
+  u64::from(width.max(height).max(1))
+    .next_power_of_two()
+    .trailing_zeros()
+}
+
+/// `level` of the image at `path`, from the cache or freshly decoded.
+fn level_image(
+  cache: &State<'_, Mutex<TileCache>>,
+  path: &Path,
+  source: &TileSource,
+  level: u32,
+  decoding: Decoding,
+) -> Result<Arc<RgbaImage>, AppError> {
+  let key = LevelKey::new(path, level);
+  if let Some(cached) = key.as_ref().and_then(|key| lock(cache).get(key)) {
+    return Ok(cached);
+  }
+
+  let image = match decoding {
+    Decoding::Jpeg => match decode_jpeg_level(path, level)? {
+      Some(image) => image,
+      None => whole_level(cache, path, source, level)?,
+    },
+    Decoding::Whole => whole_level(cache, path, source, level)?,
+    Decoding::Streamed(stream) => {
+      let (width, height) = level_size(source.width, source.height, level);
+      let rect = Rect {
+        left: 0,
+        top: 0,
+        width,
+        height,
+      };
+      stream.region(path, source, level, rect)?
+    }
+  };
+
+  let image = Arc::new(image);
+  if let Some(key) = key {
+    lock(cache).insert(key, Arc::clone(&image));
+  }
+  Ok(image)
+}
+
+/// `level` shrunk from the whole image, itself cached as level 0.
+fn whole_level(
+  cache: &State<'_, Mutex<TileCache>>,
+  path: &Path,
+  source: &TileSource,
+  level: u32,
+) -> Result<RgbaImage, AppError> {
+  if level == 0 {
+    return Ok(decode_file(path)?.into_rgba8());
+  }
+  let full = level_image(cache, path, source, 0, Decoding::Whole)?;
+  let (width, height) = level_size(full.width(), full.height(), level);
+  Ok(imageops::thumbnail(&*full, width, height))
+}
+
+/// Decodes a JPEG straight at the scale of `level`, or `None` if
+/// libjpeg-turbo can't, as with CMYK files.
+fn decode_jpeg_level(path: &Path, level: u32) -> Result<Option<RgbaImage>, AppError> {
+  check_file_len(path, max_pixels())?;
+  let data = fs::read(path).map_err(|err| AppError::read(path, &err))?;
+  let Ok(mut decompressor) = Decompressor::new() else {
+    return Ok(None);
+  };
+  let Ok(header) = decompressor.read_header(&data) else {
+    return Ok(None);
+  };
+
+  let factor = ScalingFactor::new(1, 1 << level.min(MAX_JPEG_LEVEL));
+  let scaled = header.scaled(factor);
+  check_pixels(
+    path.display(),
+    scaled.width as u32,
+    scaled.height as u32,
+    max_pixels(),
+  )?;
+
+  let mut decoded = Image {
+    pixels: vec![0; scaled.width * scaled.height * 4],
+    width: scaled.width,
+    pitch: scaled.width * 4,
+    height: scaled.height,
+    format: PixelFormat::RGBA,
+  };
+  if decompressor.set_scaling_factor(factor).is_err()
+    || decompressor
+      .decompress(&data, decoded.as_deref_mut())
+      .is_err()
+  {
+    return Ok(None);
+  }
+  let Some(image) = RgbaImage::from_raw(scaled.width as u32, scaled.height as u32, decoded.pixels)
+  else {
+    return Ok(None);
+  };
+
+  let (width, height) = level_size(header.width as u32, header.height as u32, level);
+  if image.dimensions() == (width, height) {
+    Ok(Some(image))
+  } else {
+    Ok(Some(imageops::thumbnail(&image, width, height)))
+  }
+}
+
+/// Formats that can be read a few rows at a time.
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+  Png,
+  Tiff,
+}
+
+impl Stream {
+  /// How the image at `path` can be streamed, if at all: non-interlaced
+  /// PNGs, and TIFFs holding 8 or 16-bit grey or RGB samples per pixel.
+  fn detect(path: &Path, format: ImageFormat) -> Option<Self> {
+    match format {
+      ImageFormat::Png => {
+        let reader = png_reader(path).ok()?;
+        (!reader.info().interlaced).then_some(Self::Png)
+      }
+      ImageFormat::Tiff => {
+        let mut decoder = tiff_decoder(path).ok()?;
+        let planar = decoder
+          .find_tag_unsigned::<u16>(Tag::PlanarConfiguration)
+          .ok()?;
+        // White-is-zero greys would need inverting.
+        let photometric = decoder
+          .find_tag_unsigned::<u16>(Tag::PhotometricInterpretation)
+          .ok()?;
+        let channels = tiff_channels(decoder.colortype().ok()?);
+        (planar.is_none_or(|planar| planar == 1) && photometric != Some(0) && channels.is_some())
+          .then_some(Self::Tiff)
+      }
+      _ => None,
+    }
+  }
+
+  /// Decodes `rect` of `level` by averaging the full-size pixels under it
+  /// as they are read, so only the result is ever held whole.
+  fn region(
+    self,
+    path: &Path,
+    source: &TileSource,
+    level: u32,
+    rect: Rect,
+  ) -> Result<RgbaImage, AppError> {
+    let mut downsampler = Downsampler::new(level, rect, source.width, source.height);
+    let (rows, cols) = (downsampler.rows.clone(), downsampler.cols.clone());
+    let add_row = |y, pixels: &[u8]| downsampler.add_row(y, pixels);
+    match self {
+      Self::Png => stream_png(path, rows, cols, add_row)?,
+      Self::Tiff => stream_tiff(path, rows, cols, add_row)?,
+    }
+    Ok(downsampler.finish())
+  }
+}
+
+/// Averages full-size rows, fed from the top down, into a rectangle of a
+/// coarser level.
+struct Downsampler {
+  level: u32,
+  rect: Rect,
+  /// The full-size rows and columns under `rect`.
+  rows: Range<u32>,
+  cols: Range<u32>,
+  /// Running RGBA totals and pixel counts for `pending`, the row of
+  /// `rect` being filled.
+  sums: Vec<u64>,
+  counts: Vec<u64>,
+  pending: Option<u32>,
+  image: RgbaImage,
+}
+
+impl Downsampler {
+  fn new(level: u32, rect: Rect, full_width: u32, full_height: u32) -> Self {
+    let to_full = |side: u32, limit: u32| (u64::from(side) << level).min(u64::from(limit)) as u32;
+    Self {
+      level,
+      rect,
+      rows: to_full(rect.top, full_height)..to_full(rect.top + rect.height, full_height),
+      cols: to_full(rect.left, full_width)..to_full(rect.left + rect.width, full_width),
+      sums: vec![0; rect.width as usize * 4],
+      counts: vec![0; rect.width as usize],
+      pending: None,
+      image: RgbaImage::new(rect.width, rect.height),
+    }
+  }
+
+  /// Adds full-size row `y`, given as RGBA for the columns in `cols`.
+  fn add_row(&mut self, y: u32, pixels: &[u8]) {
+    let row = (u64::from(y) >> self.level) as u32 - self.rect.top;
+    if self.pending != Some(row) {
+      self.flush();
+      self.pending = Some(row);
+    }
+    for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+      let x =
+        ((u64::from(self.cols.start) + i as u64) >> self.level) as usize - self.rect.left as usize;
+      for (sum, &sample) in self.sums[x * 4..x * 4 + 4].iter_mut().zip(pixel) {
+        *sum += u64::from(sample);
+      }
+      self.counts[x] += 1;
+    }
+  }
+
+  fn flush(&mut self) {
+    let Some(row) = self.pending.take() else {
+      return;
+    };
+    for (x, count) in self.counts.iter_mut().enumerate() {
+      if *count > 0 {
+        let sums = &mut self.sums[x * 4..x * 4 + 4];
+        let mean = |sum: &mut u64| ((*sum + *count / 2) / *count) as u8;
+        let pixel = Rgba([
+          mean(&mut sums[0]),
+          mean(&mut sums[1]),
+          mean(&mut sums[2]),
+          mean(&mut sums[3]),
+        ]);
+        self.image.put_pixel(x as u32, row, pixel);
+      }
+      *count = 0;
+    }
+    self.sums.fill(0);
+  }
+
+  fn finish(mut self) -> RgbaImage {
+    self.flush();
+    self.image
+  }
+}
+
+/// Reads `rows` of the PNG at `path` in order, passing the `cols` of each
+/// to `add_row` as RGBA.
+fn stream_png(
+  path: &Path,
+  rows: Range<u32>,
+  cols: Range<u32>,
+  mut add_row: impl FnMut(u32, &[u8]),
+) -> Result<(), AppError> {
+  let mut reader = png_reader(path)?;
+  let channels = reader.output_color_type().0.samples();
+  let samples = cols.start as usize * channels..cols.end as usize * channels;
+  let mut pixels = vec![0; cols.len() * 4];
+  for y in 0..rows.end {
+    let row = reader
+      .next_row()
+      .map_err(|err| decode_error(path, err))?
+      .ok_or_else(|| AppError::DecodeFailed(format!("truncated image: {}", path.display())))?;
+    if y >= rows.start {
+      let data = row.data().get(samples.clone()).ok_or_else(|| {
+        AppError::DecodeFailed(format!("failed to decode {}: short row", path.display()))
+      })?;
+      to_rgba(data, channels, &mut pixels);
+      add_row(y, &pixels);
+    }
+  }
+  Ok(())
+}
+
+/// Reads `rows` of the TIFF at `path` in order, passing the `cols` of each
+/// to `add_row` as RGBA. Only the strips or tiles under them are decoded.
+fn stream_tiff(
+  path: &Path,
+  rows: Range<u32>,
+  cols: Range<u32>,
+  mut add_row: impl FnMut(u32, &[u8]),
+) -> Result<(), AppError> {
+  let mut decoder = tiff_decoder(path)?;
+  let error = |err| decode_error(path, err);
+  let channels = tiff_channels(decoder.colortype().map_err(error)?).ok_or_else(|| {
+    AppError::UnsupportedFormat(format!("unsupported TIFF samples: {}", path.display()))
+  })?;
+  let (width, height) = decoder.dimensions().map_err(error)?;
+  // Strips are chunks as wide as the image.
+  let (chunk_width, chunk_height) = decoder.chunk_dimensions();
+  let across = width.div_ceil(chunk_width);
+
+  let band_width = cols.len();
+  let mut band = Vec::new();
+  for chunk_row in rows.start / chunk_height..rows.end.div_ceil(chunk_height) {
+    let band_top = chunk_row * chunk_height;
+    let band_height = chunk_height.min(height - band_top);
+    band.clear();
+    band.resize(band_width * band_height as usize * 4, 0);
+
+    for chunk_col in cols.start / chunk_width..cols.end.div_ceil(chunk_width) {
+      let index = chunk_row * across + chunk_col;
+      let (data_width, data_height) = decoder.chunk_data_dimensions(index);
+      let samples = match decoder.read_chunk(index).map_err(error)? {
+        DecodingResult::U8(samples) => samples,
+        DecodingResult::U16(samples) => samples.iter().map(|&sample| (sample >> 8) as u8).collect(),
+        _ => {
+          return Err(AppError::UnsupportedFormat(format!(
+            "unsupported TIFF samples: {}",
+            path.display()
+          )))
+        }
+      };
+      let (data_width, data_height) = (data_width as usize, data_height as usize);
+      if samples.len() < data_width * data_height * channels {
+        return Err(AppError::DecodeFailed(format!(
+          "failed to decode {}: short chunk {}",
+          path.display(),
+          index
+        )));
+      }
+
+      // The columns of this chunk that are in `cols`.
+      let chunk_left = (chunk_col * chunk_width) as usize;
+      let from = (cols.start as usize).max(chunk_left);
+      let to = (cols.end as usize).min(chunk_left + data_width);
+      for row in 0..data_height.min(band_height as usize) {
+        let start = row * data_width;
+        let at = (row * band_width + from - cols.start as usize) * 4;
+        to_rgba(
+          &samples[(start + from - chunk_left) * channels..(start + to - chunk_left) * channels],
+          channels,
+          &mut band[at..at + (to - from) * 4],
+        );
+      }
+    }
+
+    for (row, pixels) in band.chunks_exact(band_width * 4).enumerate() {
+      let y = band_top + row as u32;
+      if rows.contains(&y) {
+        add_row(y, pixels);
+      }
+    }
+  }
+  Ok(())
+}
+
+fn png_reader(path: &Path) -> Result<::png::Reader<BufReader<File>>, AppError> {
+  let file = File::open(path).map_err(|err| AppError::read(path, &err))?;
+  let mut decoder = ::png::Decoder::new(BufReader::new(file));
+  decoder.set_transformations(Transformations::normalize_to_color8());
+  decoder.read_info().map_err(|err| decode_error(path, err))
+}
+
+fn tiff_decoder(path: &Path) -> Result<TiffDecoder<BufReader<File>>, AppError> {
+  let file = File::open(path).map_err(|err| AppError::read(path, &err))?;
+  TiffDecoder::new(BufReader::new(file)).map_err(|err| decode_error(path, err))
+}
+
+/// Samples per pixel for the TIFF colour types `stream_tiff` reads.
+fn tiff_channels(color: TiffColor) -> Option<usize> {
+  match color {
+    TiffColor::Gray(8 | 16) => Some(1),
+    TiffColor::GrayA(8 | 16) => Some(2),
+    TiffColor::RGB(8 | 16) => Some(3),
+    TiffColor::RGBA(8 | 16) => Some(4),
+    _ => None,
+  }
+}
+
+/// Expands grey, grey and alpha, RGB or RGBA `samples` into RGBA `out`.
+fn to_rgba(samples: &[u8], channels: usize, out: &mut [u8]) {
+  for (pixel, rgba) in samples.chunks_exact(channels).zip(out.chunks_exact_mut(4)) {
+    let expanded = match *pixel {
+      [grey] => [grey, grey, grey, 255],
+      [grey, alpha] => [grey, grey, grey, alpha],
+      [r, g, b] => [r, g, b, 255],
+      [r, g, b, a, ..] => [r, g, b, a],
+      [] => [0; 4],
+    };
+    rgba.copy_from_slice(&expanded);
+  }
+}
+
+fn decode_error(path: &Path, err: impl std::fmt::Display) -> AppError {
+  AppError::DecodeFailed(format!("failed to decode {}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::path::PathBuf;
+
+  fn pattern(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+      Rgba([(x * 3) as u8, (y * 5) as u8, (x ^ y) as u8, 255])
+    })
+  }
+
+  fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("image-pro-{}-{}", std::process::id(), name))
+  }
+
+  /// The rounded mean of the pixels in a `width` by `height` block.
+  fn block_mean(image: &RgbaImage, left: u32, top: u32, width: u32, height: u32) -> Rgba<u8> {
+    let block = imageops::crop_imm(image, left, top, width, height).to_image();
+    let count = u64::from(block.width() * block.height());
+    let mut sums = [0u64; 4];
+    for pixel in block.pixels() {
+      for (sum, &sample) in sums.iter_mut().zip(&pixel.0) {
+        *sum += u64::from(sample);
+      }
+    }
+    Rgba(sums.map(|sum| ((sum + count / 2) / count) as u8))
+  }
+
+  /// Streams `rect` of `level` with a budget too small for anything else.
+  fn streamed(path: &Path, level: u32, rect: Rect) -> RgbaImage {
+    let source = TileSource::open(path).unwrap();
+    let Some(Decoding::Streamed(stream)) = source.decoding(level, 16) else {
+      panic!("{} can't be streamed", path.display());
+    };
+    stream.region(path, &source, level, rect).unwrap()
+  }
+
+  fn rect(left: u32, top: u32, width: u32, height: u32) -> Rect {
+    Rect {
+      left,
+      top,
+      width,
+      height,
+    }
+  }
+
+  fn assert_streams(path: &Path, image: &RgbaImage) {
+    let tile = streamed(path, 0, rect(10, 5, 25, 14));
+    assert_eq!(tile, imageops::crop_imm(image, 10, 5, 25, 14).to_image());
+
+    // Each pixel of level 1 averages the 2x2 block under it, or what is
+    // left of one at the right and bottom edges.
+    let (width, height) = level_size(image.width(), image.height(), 1);
+    let level = streamed(path, 1, rect(0, 0, width, height));
+    for (x, y, pixel) in level.enumerate_pixels() {
+      let block_width = 2.min(image.width() - x * 2);
+      let block_height = 2.min(image.height() - y * 2);
+      assert_eq!(
+        *pixel,
+        block_mean(image, x * 2, y * 2, block_width, block_height),
+        "level 1 pixel ({}, {})",
+        x,
+        y
+      );
+    }
+  }
+
+  #[test]
+  fn png_tiles_stream_past_the_budget() {
+    let image = pattern(41, 23);
+    let path = temp_path("stream.png");
+    image.save(&path).unwrap();
+    assert_streams(&path, &image);
+    let _ = fs::remove_file(&path);
+  }
+
+  #[test]
+  fn tiff_strips_stream_past_the_budget() {
+    // Wide enough that the encoder splits it into several strips.
+    let image = pattern(200, 23);
+    let path = temp_path("strips.tiff");
+    image.save(&path).unwrap();
+    assert_streams(&path, &image);
+    let _ = fs::remove_file(&path);
+  }
+
+  #[test]
+  fn tiff_tiles_stream_past_the_budget() {
+    let image = pattern(41, 23);
+    let path = temp_path("tiles.tiff");
+    fs::write(&path, tiled_tiff(&image)).unwrap();
+    assert_streams(&path, &image);
+    let _ = fs::remove_file(&path);
+  }
+
+  /// An uncompressed RGB TIFF of `image` in 16x16 tiles.
+  fn tiled_tiff(image: &RgbaImage) -> Vec<u8> {
+    const TILE: u32 = 16;
+    let across = image.width().div_ceil(TILE);
+    let down = image.height().div_ceil(TILE);
+    let tiles = across * down;
+    let tile_len = TILE * TILE * 3;
+
+    let entries: [(u16, u16, u32, u32); 11] = [
+      (256, 3, 1, image.width()),
+      (257, 3, 1, image.height()),
+      (258, 3, 3, 146),
+      (259, 3, 1, 1),
+      (262, 3, 1, 2),
+      (277, 3, 1, 3),
+      (284, 3, 1, 1),
+      (322, 3, 1, TILE),
+      (323, 3, 1, TILE),
+      (324, 4, tiles, 146 + 6),
+      (325, 4, tiles, 146 + 6 + tiles * 4),
+    ];
+    let data_start = 146 + 6 + tiles * 8;
+
+    let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+    tiff.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, kind, count, value) in entries {
+      tiff.extend_from_slice(&tag.to_le_bytes());
+      tiff.extend_from_slice(&kind.to_le_bytes());
+      tiff.extend_from_slice(&count.to_le_bytes());
+      tiff.extend_from_slice(&value.to_le_bytes());
+    }
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+    assert_eq!(tiff.len(), 146);
+    for _ in 0..3 {
+      tiff.extend_from_slice(&8u16.to_le_bytes());
+    }
+    for tile in 0..tiles {
+      tiff.extend_from_slice(&(data_start + tile * tile_len).to_le_bytes());
+    }
+    for _ in 0..tiles {
+      tiff.extend_from_slice(&tile_len.to_le_bytes());
+    }
+    for tile_y in 0..down {
+      for tile_x in 0..across {
+        for y in tile_y * TILE..(tile_y + 1) * TILE {
+          for x in tile_x * TILE..(tile_x + 1) * TILE {
+            let pixel = if x < image.width() && y < image.height() {
+              image.get_pixel(x, y).0
+            } else {
+              [0; 4]
+            };
+            tiff.extend_from_slice(&pixel[..3]);
+          }
+        }
+      }
+    }
+    tiff
+  }
+
+  #[test]
+  fn jpeg_levels_are_unavailable_past_the_budget() {
+    let path = temp_path("levels.jpg");
+    image::DynamicImage::ImageRgba8(pattern(64, 32))
+      .to_rgb8()
+      .save(&path)
+      .unwrap();
+    let source = TileSource::open(&path).unwrap();
+    let _ = fs::remove_file(&path);
+
+    // Level 1 is 32x16, decoded straight at half size.
+    assert!(source.decoding(0, 512).is_none());
+    assert!(matches!(source.decoding(1, 512), Some(Decoding::Jpeg)));
+    assert!(matches!(source.decoding(0, 2048), Some(Decoding::Whole)));
+    assert!(matches!(
+      source.unavailable(&path, 0, 512),
+      AppError::TooLarge { required: 2048, .. }
+    ));
+  }
+}
@@ -0,0 +1,715 @@
+//! Geometric transforms on RGBA buffers.
+
+use std::sync::Mutex;
+
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::file::{check_pixels, max_pixels};
+use super::{edit_source, finish_edit, Edited};
+use crate::documents::{DocumentId, Documents};
+use crate::error::AppError;
+use crate::logging::traced;
+use crate::orientation::Orientation;
+
+/// A rectangle of pixels, half-open like `crop_image`'s.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CropRect {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32,
+}
+
+/// An image cropped by `trim_borders`, and where in the original it was.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrimmedImage {
+  #[serde(flatten)]
+  pub image: Edited,
+  pub crop: CropRect,
+}
+
+/// Maps a filter name from the UI to a resampling filter, falling back to
+/// Lanczos3 for anything unrecognised.
+fn parse_filter(name: &str) -> FilterType {
+  match name.to_ascii_lowercase().as_str() {
+    "nearest" => FilterType::Nearest,
+    "triangle" => FilterType::Triangle,
+    "catmull-rom" => FilterType::CatmullRom,
+    "gaussian" => FilterType::Gaussian,
+    "lanczos3" => FilterType::Lanczos3,
+    other => {
+      tracing::warn!("unknown resize filter {:?}, using lanczos3", other);
+      FilterType::Lanczos3
+    }
+  }
+}
+
+/// Resamples an RGBA buffer to exactly `new_width` x `new_height`.
+///
+/// `filter` is one of "nearest", "triangle", "catmull-rom", "gaussian" or
+/// "lanczos3". Nearest copies source pixels as-is, which keeps pixel art
+/// sharp when upscaling.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn resize_image(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  new_width: u32,
+  new_height: u32,
+  filter: String,
+) -> Result<Edited, AppError> {
+  traced("resize_image", async move {
+    if new_width == 0 || new_height == 0 {
+      return Err(AppError::InvalidArgument(format!(
+        "invalid target size {}x{}",
+        new_width, new_height
+      )));
+    }
+    check_pixels("the resized image", new_width, new_height, max_pixels())?;
+
+    let image = edit_source(&documents, doc_id, data, width, height)?;
+    let resized = imageops::resize(&image, new_width, new_height, parse_filter(&filter));
+    finish_edit(&documents, doc_id, resized)
+  })
+  .await
+}
+
+/// Resizes an RGBA buffer relative to a `max_w` x `max_h` box.
+///
+/// `mode` is one of:
+/// - "fit": scale to fit inside the box keeping aspect ratio; one side may
+///   come out shorter than the box.
+/// - "fill": scale to cover the box keeping aspect ratio, then crop the
+///   overflow equally from both sides so the result is exactly the box.
+/// - "stretch": scale to exactly the box, ignoring aspect ratio.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn resize_to_bounds(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  max_w: u32,
+  max_h: u32,
+  mode: String,
+) -> Result<Edited, AppError> {
+  traced("resize_to_bounds", async move {
+    if max_w == 0 || max_h == 0 {
+      return Err(AppError::InvalidArgument(format!(
+        "invalid bounds {}x{}",
+        max_w, max_h
+      )));
+    }
+    check_pixels("the resized image", max_w, max_h, max_pixels())?;
+
+    let image = edit_source(&documents, doc_id, data, width, height)?;
+    let resized = resize_within(&image, max_w, max_h, &mode)?;
+    finish_edit(&documents, doc_id, resized)
+  })
+  .await
+}
+
+/// Crops an RGBA buffer to the `w` x `h` rectangle whose top-left pixel is
+/// (`x`, `y`).
+///
+/// The rectangle is half-open, so `x + w == width` reaches the right edge
+/// exactly. Anything past the image is clamped away; only a rectangle with
+/// nothing left after clamping is an error.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn crop_image(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  x: u32,
+  y: u32,
+  w: u32,
+  h: u32,
+) -> Result<Edited, AppError> {
+  traced("crop_image", async move {
+    let image = edit_source(&documents, doc_id, data, width, height)?;
+    let cropped = crop(&image, x, y, w, h)?;
+    finish_edit(&documents, doc_id, cropped)
+  })
+  .await
+}
+
+/// Trims a uniform border off an RGBA buffer, returning the cropped image
+/// and the rectangle it was cut from.
+///
+/// The border colour is `background`, or else the colour most of the four
+/// corners share. A pixel counts as border when each channel is within
+/// `tolerance` of it, which absorbs scanner noise; fully transparent pixels
+/// match each other whatever their colour. An image with nothing but
+/// border is an error.
+#[tauri::command]
+pub async fn trim_borders(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  tolerance: u8,
+  background: Option<[u8; 4]>,
+) -> Result<TrimmedImage, AppError> {
+  traced("trim_borders", async move {
+    let image = edit_source(&documents, doc_id, data, width, height)?;
+    let crop = trim_rect(&image, tolerance, background)?;
+    let trimmed = imageops::crop_imm(&image, crop.x, crop.y, crop.width, crop.height).to_image();
+    Ok(TrimmedImage {
+      image: finish_edit(&documents, doc_id, trimmed)?,
+      crop,
+    })
+  })
+  .await
+}
+
+/// Mirrors an RGBA buffer. `axis` is "horizontal" (left and right swap) or
+/// "vertical" (top and bottom swap).
+#[tauri::command]
+pub async fn flip_image(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  axis: String,
+) -> Result<Edited, AppError> {
+  traced("flip_image", async move {
+    let mut image = edit_source(&documents, doc_id, data, width, height)?;
+    match axis.as_str() {
+      "horizontal" => imageops::flip_horizontal_in_place(&mut image),
+      "vertical" => imageops::flip_vertical_in_place(&mut image),
+      other => {
+        return Err(AppError::InvalidArgument(format!(
+          "unknown flip axis: {}",
+          other
+        )))
+      }
+    }
+    finish_edit(&documents, doc_id, image)
+  })
+  .await
+}
+
+/// Turns an RGBA buffer upright according to its EXIF `orientation` tag
+/// (1-8), so it can be shown or saved without the tag.
+///
+/// Tags 2, 4, 5 and 7 are mirrored, and 5 to 8 swap the width and height,
+/// which the returned `ImageData` reflects.
+#[tauri::command]
+pub async fn auto_orient(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  orientation: u16,
+) -> Result<Edited, AppError> {
+  traced("auto_orient", async move {
+    let transform = Orientation::from_exif(orientation).ok_or_else(|| {
+      AppError::InvalidArgument(format!("EXIF orientation must be 1-8, got {}", orientation))
+    })?;
+    let image = edit_source(&documents, doc_id, data, width, height)?;
+    finish_edit(&documents, doc_id, transform.apply(image))
+  })
+  .await
+}
+
+/// Rotates an RGBA buffer clockwise by `degrees`, growing the canvas so
+/// none of the image is cut off and filling the uncovered corners with
+/// `background`.
+///
+/// Multiples of 90 degrees are exact pixel moves, identical to what
+/// `rotate_jpeg_lossless` produces. Any other angle is resampled
+/// bilinearly, with the edges blended into `background`.
+#[tauri::command]
+pub async fn rotate_image(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  degrees: f32,
+  background: [u8; 4],
+) -> Result<Edited, AppError> {
+  traced("rotate_image", async move {
+    if !degrees.is_finite() {
+      return Err(AppError::InvalidArgument(format!(
+        "invalid rotation angle: {}",
+        degrees
+      )));
+    }
+
+    let image = edit_source(&documents, doc_id, data, width, height)?;
+    let rotated = rotate(image, degrees, Rgba(background))?;
+    finish_edit(&documents, doc_id, rotated)
+  })
+  .await
+}
+
+/// Warps the quadrilateral `corners` of an RGBA buffer onto an `out_w` by
+/// `out_h` rectangle, undoing the keystone of a document or whiteboard
+/// photographed at an angle.
+///
+/// `corners` are pixel coordinates in the order top-left, top-right,
+/// bottom-right, bottom-left, as they should end up in the output. They
+/// must form a convex quadrilateral; three nearly in line are rejected
+/// rather than blowing the output up. The quad may reach outside the
+/// source, and those parts of the output are transparent.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn perspective_correct(
+  documents: State<'_, Mutex<Documents>>,
+  doc_id: Option<DocumentId>,
+  data: Vec<u8>,
+  width: u32,
+  height: u32,
+  corners: [[f32; 2]; 4],
+  out_w: u32,
+  out_h: u32,
+) -> Result<Edited, AppError> {
+  traced("perspective_correct", async move {
+    if out_w == 0 || out_h == 0 {
+      return Err(AppError::InvalidArgument(format!(
+        "invalid output size {}x{}",
+        out_w, out_h
+      )));
+    }
+    check_pixels("the corrected image", out_w, out_h, max_pixels())?;
+    let corners = corners.map(|[x, y]| [f64::from(x), f64::from(y)]);
+    if corners.iter().flatten().any(|value| !value.is_finite()) {
+      return Err(AppError::InvalidArgument(
+        "corners must be finite numbers".into(),
+      ));
+    }
+    let homography = Homography::square_to_quad(&corners).ok_or_else(|| {
+      AppError::InvalidArgument("the corners must form a convex quadrilateral".into())
+    })?;
+
+    let image = edit_source(&documents, doc_id, data, width, height)?;
+    let transparent = Rgba([0, 0, 0, 0]);
+    let (out_wf, out_hf) = (f64::from(out_w), f64::from(out_h));
+    let warped = RgbaImage::from_fn(out_w, out_h, |x, y| {
+      let u = (f64::from(x) + 0.5) / out_wf;
+      let v = (f64::from(y) + 0.5) / out_hf;
+      match homography.map(u, v) {
+        Some((sx, sy)) => sample_bilinear(&image, sx - 0.5, sy - 0.5, transparent),
+        None => transparent,
+      }
+    });
+    finish_edit(&documents, doc_id, warped)
+  })
+  .await
+}
+
+/// Resizes `image` relative to a `max_w` x `max_h` box as
+/// `resize_to_bounds` describes.
+fn resize_within(
+  image: &RgbaImage,
+  max_w: u32,
+  max_h: u32,
+  mode: &str,
+) -> Result<RgbaImage, AppError> {
+  let (width, height) = image.dimensions();
+  if width == 0 || height == 0 {
+    return Err(AppError::InvalidArgument(
+      "cannot resize an empty image".into(),
+    ));
+  }
+
+  // Compare aspect ratios exactly with integer cross-multiplication so equal
+  // ratios always take the no-crop path.
+  let wider = u64::from(width) * u64::from(max_h) >= u64::from(height) * u64::from(max_w);
+
+  Ok(match mode {
+    "fit" => {
+      let (w, h) = if wider {
+        (max_w, scale_rounded(height, max_w, width))
+      } else {
+        (scale_rounded(width, max_h, height), max_h)
+      };
+      imageops::resize(image, w, h, FilterType::Lanczos3)
+    }
+    "fill" => {
+      let (w, h) = if wider {
+        (scale_rounded(width, max_h, height), max_h)
+      } else {
+        (max_w, scale_rounded(height, max_w, width))
+      };
+      // Covering a box far narrower than the image scales it up a long way
+      // before the crop.
+      check_pixels("the scaled image", w, h, max_pixels())?;
+      let scaled = imageops::resize(image, w, h, FilterType::Lanczos3);
+      let x = (w - max_w) / 2;
+      let y = (h - max_h) / 2;
+      imageops::crop_imm(&scaled, x, y, max_w, max_h).to_image()
+    }
+    "stretch" => imageops::resize(image, max_w, max_h, FilterType::Lanczos3),
+    other => {
+      return Err(AppError::InvalidArgument(format!(
+        "unknown resize mode: {}",
+        other
+      )))
+    }
+  })
+}
+
+/// The part of `image` inside the `w` x `h` rectangle at (`x`, `y`), as
+/// `crop_image` describes.
+pub(crate) fn crop(
+  image: &RgbaImage,
+  x: u32,
+  y: u32,
+  w: u32,
+  h: u32,
+) -> Result<RgbaImage, AppError> {
+  let (width, height) = image.dimensions();
+  let right = x.saturating_add(w).min(width);
+  let bottom = y.saturating_add(h).min(height);
+  if x >= right || y >= bottom {
+    return Err(AppError::InvalidArgument(format!(
+      "crop rectangle {}x{} at ({}, {}) is outside the {}x{} image",
+      w, h, x, y, width, height
+    )));
+  }
+  Ok(imageops::crop_imm(image, x, y, right - x, bottom - y).to_image())
+}
+
+/// The rectangle `trim_borders` keeps of `image`.
+fn trim_rect(
+  image: &RgbaImage,
+  tolerance: u8,
+  background: Option<[u8; 4]>,
+) -> Result<CropRect, AppError> {
+  let (width, height) = image.dimensions();
+  if width == 0 || height == 0 {
+    return Err(AppError::InvalidArgument(format!(
+      "can't trim a {}x{} image",
+      width, height
+    )));
+  }
+
+  let matches = |a: [u8; 4], b: [u8; 4]| {
+    (a[3] == 0 && b[3] == 0) || a.iter().zip(&b).all(|(a, b)| a.abs_diff(*b) <= tolerance)
+  };
+  let background = background.unwrap_or_else(|| {
+    let corners = [
+      (0, 0),
+      (width - 1, 0),
+      (0, height - 1),
+      (width - 1, height - 1),
+    ]
+    .map(|(x, y)| image.get_pixel(x, y).0);
+    // The first corner wins ties, so the top left decides when no two
+    // corners agree.
+    let agreeing = |corner: &[u8; 4]| {
+      corners
+        .iter()
+        .filter(|other| matches(*corner, **other))
+        .count()
+    };
+    corners
+      .iter()
+      .copied()
+      .rev()
+      .max_by_key(agreeing)
+      .unwrap_or(corners[0])
+  });
+  let is_content = |pixel: &Rgba<u8>| !matches(pixel.0, background);
+
+  let rows: Vec<_> = image.rows().collect();
+  let Some(top) = rows.iter().position(|row| row.clone().any(is_content)) else {
+    return Err(AppError::InvalidArgument(
+      "the image is all background; trimming would leave nothing".into(),
+    ));
+  };
+  let bottom = rows
+    .iter()
+    .rposition(|row| row.clone().any(is_content))
+    .unwrap_or(top);
+  let (mut left, mut right) = (width - 1, 0);
+  for row in &rows[top..=bottom] {
+    if let Some(first) = row.clone().position(is_content) {
+      let last = row.clone().rposition(is_content).unwrap_or(first);
+      left = left.min(first as u32);
+      right = right.max(last as u32);
+    }
+  }
+
+  Ok(CropRect {
+    x: left,
+    y: top as u32,
+    width: right - left + 1,
+    height: (bottom - top) as u32 + 1,
+  })
+}
+
+/// Rotates `image` clockwise by `degrees` as `rotate_image` describes.
+fn rotate(image: RgbaImage, degrees: f32, background: Rgba<u8>) -> Result<RgbaImage, AppError> {
+  let degrees = degrees.rem_euclid(360.0);
+  Ok(if degrees == 0.0 {
+    image
+  } else if degrees == 90.0 {
+    imageops::rotate90(&image)
+  } else if degrees == 180.0 {
+    imageops::rotate180(&image)
+  } else if degrees == 270.0 {
+    imageops::rotate270(&image)
+  } else {
+    let (width, height) = rotated_size(image.width(), image.height(), degrees);
+    check_pixels("the rotated image", width, height, max_pixels())?;
+    rotate_bilinear(&image, degrees, background)
+  })
+}
+
+/// A projective map from the unit square to a quadrilateral:
+/// `x = (a u + b v + c) / (g u + h v + 1)`, likewise `y` with `d e f`.
+struct Homography {
+  a: f64,
+  b: f64,
+  c: f64,
+  d: f64,
+  e: f64,
+  f: f64,
+  g: f64,
+  h: f64,
+}
+
+impl Homography {
+  /// The map taking (0, 0), (1, 0), (1, 1) and (0, 1) to `quad` in order,
+  /// or `None` unless `quad` is convex with no three corners (nearly) in
+  /// line.
+  ///
+  /// This is Heckbert's closed form, which needs no matrix inversion. The
+  /// convexity check bounds every denominator away from zero, so no NaN or
+  /// infinity can come out of it.
+  fn square_to_quad(quad: &[[f64; 2]; 4]) -> Option<Self> {
+    // The turn at every corner must be the same way, and by a clear margin
+    // relative to the edges meeting there.
+    let mut turn_sign = 0.0;
+    for i in 0..4 {
+      let [px, py] = quad[(i + 3) % 4];
+      let [x, y] = quad[i];
+      let [nx, ny] = quad[(i + 1) % 4];
+      let (ax, ay, bx, by) = (x - px, y - py, nx - x, ny - y);
+      let cross = ax * by - ay * bx;
+      if cross.abs() <= 1e-6 * ax.hypot(ay) * bx.hypot(by) || cross * turn_sign < 0.0 {
+        return None;
+      }
+      turn_sign = cross.signum();
+    }
+
+    let [[x0, y0], [x1, y1], [x2, y2], [x3, y3]] = *quad;
+    let (dx1, dy1) = (x1 - x2, y1 - y2);
+    let (dx2, dy2) = (x3 - x2, y3 - y2);
+    let (dx3, dy3) = (x0 - x1 + x2 - x3, y0 - y1 + y2 - y3);
+    let den = dx1 * dy2 - dx2 * dy1;
+    let g = (dx3 * dy2 - dx2 * dy3) / den;
+    let h = (dx1 * dy3 - dx3 * dy1) / den;
+    Some(Self {
+      a: x1 - x0 + g * x1,
+      b: x3 - x0 + h * x3,
+      c: x0,
+      d: y1 - y0 + g * y1,
+      e: y3 - y0 + h * y3,
+      f: y0,
+      g,
+      h,
+    })
+  }
+
+  /// The image of (`u`, `v`), or `None` where it would be at infinity.
+  fn map(&self, u: f64, v: f64) -> Option<(f64, f64)> {
+    let w = self.g * u + self.h * v + 1.0;
+    if w <= f64::EPSILON {
+      return None;
+    }
+    Some((
+      (self.a * u + self.b * v + self.c) / w,
+      (self.d * u + self.e * v + self.f) / w,
+    ))
+  }
+}
+
+/// Rotates `image` clockwise by `degrees` onto a canvas just big enough to
+/// hold it, sampling bilinearly.
+fn rotate_bilinear(image: &RgbaImage, degrees: f32, background: Rgba<u8>) -> RgbaImage {
+  let (sin, cos) = f64::from(degrees).to_radians().sin_cos();
+  let (w, h) = (f64::from(image.width()), f64::from(image.height()));
+  let (out_w, out_h) = rotated_size(image.width(), image.height(), degrees);
+
+  let (cx, cy) = (w / 2.0, h / 2.0);
+  let (out_cx, out_cy) = (f64::from(out_w) / 2.0, f64::from(out_h) / 2.0);
+
+  RgbaImage::from_fn(out_w, out_h, |x, y| {
+    // Map the centre of each output pixel back into the source.
+    let dx = f64::from(x) + 0.5 - out_cx;
+    let dy = f64::from(y) + 0.5 - out_cy;
+    let sx = dx * cos + dy * sin + cx - 0.5;
+    let sy = -dx * sin + dy * cos + cy - 0.5;
+    sample_bilinear(image, sx, sy, background)
+  })
+}
+
+/// The canvas `rotate_bilinear` needs for a `width` by `height` image.
+fn rotated_size(width: u32, height: u32, degrees: f32) -> (u32, u32) {
+  let (sin, cos) = f64::from(degrees).to_radians().sin_cos();
+  let (w, h) = (f64::from(width), f64::from(height));
+  // Shave a little off before rounding up so float noise on an exact fit
+  // doesn't add a row of background.
+  let out_w = ((w * cos.abs() + h * sin.abs()) - 1e-6).ceil().max(1.0) as u32;
+  let out_h = ((w * sin.abs() + h * cos.abs()) - 1e-6).ceil().max(1.0) as u32;
+  (out_w, out_h)
+}
+
+/// Bilinear sample of `image` at (`x`, `y`) in pixel-centre coordinates,
+/// treating everything outside the image as `background`.
+///
+/// Interpolation is done on premultiplied values so transparent pixels
+/// don't bleed their colour into the edges.
+fn sample_bilinear(image: &RgbaImage, x: f64, y: f64, background: Rgba<u8>) -> Rgba<u8> {
+  let (x0, y0) = (x.floor(), y.floor());
+  let (fx, fy) = (x - x0, y - y0);
+  let (x0, y0) = (x0 as i64, y0 as i64);
+
+  let mut acc = [0.0f64; 4];
+  for (tx, ty, weight) in [
+    (x0, y0, (1.0 - fx) * (1.0 - fy)),
+    (x0 + 1, y0, fx * (1.0 - fy)),
+    (x0, y0 + 1, (1.0 - fx) * fy),
+    (x0 + 1, y0 + 1, fx * fy),
+  ] {
+    if weight == 0.0 {
+      continue;
+    }
+    let inside =
+      tx >= 0 && ty >= 0 && tx < i64::from(image.width()) && ty < i64::from(image.height());
+    let pixel = if inside {
+      *image.get_pixel(tx as u32, ty as u32)
+    } else {
+      background
+    };
+    let alpha = f64::from(pixel[3]) / 255.0;
+    for (sum, &channel) in acc[..3].iter_mut().zip(&pixel.0[..3]) {
+      *sum += weight * alpha * f64::from(channel);
+    }
+    acc[3] += weight * alpha;
+  }
+
+  let alpha = acc[3];
+  if alpha <= 0.0 {
+    return Rgba([0, 0, 0, 0]);
+  }
+  let channel = |sum: f64| (sum / alpha).round().clamp(0.0, 255.0) as u8;
+  Rgba([
+    channel(acc[0]),
+    channel(acc[1]),
+    channel(acc[2]),
+    (alpha * 255.0).round().clamp(0.0, 255.0) as u8,
+  ])
+}
+
+/// `value * num / den`, rounded to nearest and kept within 1 and
+/// `u32::MAX`.
+fn scale_rounded(value: u32, num: u32, den: u32) -> u32 {
+  let den = u64::from(den);
+  let scaled = (u64::from(value) * u64::from(num) + den / 2) / den;
+  scaled.clamp(1, u64::from(u32::MAX)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn pattern(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+      Rgba([(x * 20) as u8, (y * 30) as u8, (x + y) as u8, 255])
+    })
+  }
+
+  #[test]
+  fn unknown_filters_fall_back_to_lanczos3() {
+    assert_eq!(parse_filter("Nearest"), FilterType::Nearest);
+    assert_eq!(parse_filter("bicubic"), FilterType::Lanczos3);
+  }
+
+  #[test]
+  fn crops_are_clamped_at_the_right_and_bottom_edges() {
+    let image = pattern(10, 8);
+    let cropped = crop(&image, 6, 5, 100, 100).unwrap();
+    assert_eq!(cropped, imageops::crop_imm(&image, 6, 5, 4, 3).to_image());
+    assert!(matches!(
+      crop(&image, 10, 0, 1, 1),
+      Err(AppError::InvalidArgument(_))
+    ));
+  }
+
+  #[test]
+  fn fill_does_not_crop_a_matching_aspect_ratio() {
+    let image = pattern(40, 20);
+    let filled = resize_within(&image, 20, 10, "fill").unwrap();
+    assert_eq!(
+      filled,
+      imageops::resize(&image, 20, 10, FilterType::Lanczos3)
+    );
+  }
+
+  #[test]
+  fn trimming_an_all_background_image_fails() {
+    let image = RgbaImage::from_pixel(6, 4, Rgba([255, 255, 255, 255]));
+    assert!(matches!(
+      trim_rect(&image, 0, None),
+      Err(AppError::InvalidArgument(_))
+    ));
+  }
+
+  #[test]
+  fn quarter_turns_move_pixels_exactly() {
+    let image = pattern(3, 2);
+    let background = Rgba([0, 0, 0, 0]);
+    let turned = rotate(image.clone(), 90.0, background).unwrap();
+    assert_eq!(turned.dimensions(), (2, 3));
+    // The bottom-left pixel ends up at the top left.
+    assert_eq!(turned.get_pixel(0, 0), image.get_pixel(0, 1));
+    assert_eq!(
+      rotate(image.clone(), -180.0, background).unwrap(),
+      imageops::rotate180(&image)
+    );
+    assert_eq!(
+      rotate(image.clone(), 270.0, background).unwrap(),
+      imageops::rotate270(&image)
+    );
+  }
+
+  #[test]
+  fn degenerate_and_concave_quads_are_rejected() {
+    let square = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]];
+    assert!(Homography::square_to_quad(&square).is_some());
+    let collinear = [[0.0, 0.0], [5.0, 0.0], [10.0, 0.0], [0.0, 10.0]];
+    assert!(Homography::square_to_quad(&collinear).is_none());
+    let concave = [[0.0, 0.0], [10.0, 0.0], [3.0, 3.0], [0.0, 10.0]];
+    assert!(Homography::square_to_quad(&concave).is_none());
+    let crossed = [[0.0, 0.0], [10.0, 10.0], [10.0, 0.0], [0.0, 10.0]];
+    assert!(Homography::square_to_quad(&crossed).is_none());
+  }
+
+  #[test]
+  fn outputs_over_the_pixel_budget_are_refused() {
+    let image = pattern(4, 4);
+    // Covering a 1-pixel-wide box scales the height up enormously.
+    assert!(matches!(
+      resize_within(&image, 1, 1_000_000, "fill"),
+      Err(AppError::TooLarge { .. })
+    ));
+  }
+}
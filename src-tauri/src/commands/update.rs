@@ -0,0 +1,26 @@
+//! Installing the release found by the startup update check.
+
+use tauri::{AppHandle, State};
+
+use crate::error::AppError;
+use crate::logging::traced;
+use crate::updater::{self, PendingUpdate};
+
+/// Downloads and installs the newest release, emitting
+/// `update-download-progress` with `{ downloaded, total }` in bytes along
+/// the way. `total` is null if the server didn't say.
+///
+/// The release announced by `update-available` is installed if there was
+/// one; otherwise the endpoint is checked again first. On Windows the
+/// installer closes the app; elsewhere the new version runs after a
+/// restart. Fails if the updater isn't configured (see `updater`).
+#[tauri::command]
+pub async fn install_update(
+  app: AppHandle,
+  pending: State<'_, PendingUpdate>,
+) -> Result<(), AppError> {
+  traced("install_update", async move {
+    updater::install(&app, &pending).await
+  })
+  .await
+}
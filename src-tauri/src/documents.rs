@@ -0,0 +1,179 @@
+//! Open documents, one per editor tab, each with its own pixels and undo
+//! history.
+//!
+//! The frontend refers to documents by id and only fetches pixels when it
+//! needs to draw them: editing commands given a `doc_id` work on the
+//! document's own buffer. Closing a document drops its buffer and history
+//! straight away.
+
+use image::RgbaImage;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::error::AppError;
+use crate::history::{self, History, Snapshot};
+
+pub type DocumentId = u64;
+
+/// A new state for a document along with its undo snapshot, compressed
+/// before taking the lock so other documents aren't held up meanwhile.
+#[derive(Debug)]
+pub struct Edit {
+  image: RgbaImage,
+  snapshot: Snapshot,
+}
+
+impl Edit {
+  pub fn new(image: RgbaImage) -> Result<Self, AppError> {
+    Ok(Self {
+      snapshot: Snapshot::new(&image)?,
+      image,
+    })
+  }
+}
+
+#[derive(Debug)]
+pub struct Document {
+  name: String,
+  image: RgbaImage,
+  history: History,
+}
+
+impl Document {
+  pub fn image(&self) -> &RgbaImage {
+    &self.image
+  }
+
+  /// Makes the edited image the current state, recording it for undo.
+  pub fn edit(&mut self, edit: Edit) {
+    self.history.push(edit.snapshot);
+    self.image = edit.image;
+  }
+
+  /// Steps back one state and returns it.
+  pub fn undo(&mut self) -> Result<&RgbaImage, AppError> {
+    self.image = self.history.undo()?;
+    Ok(&self.image)
+  }
+
+  /// Steps forward one state and returns it.
+  pub fn redo(&mut self) -> Result<&RgbaImage, AppError> {
+    self.image = self.history.redo()?;
+    Ok(&self.image)
+  }
+
+  /// Bytes held by the current pixels and the undo history.
+  pub fn memory_usage(&self) -> usize {
+    self.image.len() + self.history.memory_usage()
+  }
+}
+
+/// What `list_documents` reports about each document.
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentInfo {
+  pub id: DocumentId,
+  pub name: String,
+  pub width: u32,
+  pub height: u32,
+  /// Bytes held by the pixels and undo history together.
+  pub memory_bytes: usize,
+  pub can_undo: bool,
+  pub can_redo: bool,
+}
+
+/// Every open document by id, held in managed state.
+#[derive(Debug)]
+pub struct Documents {
+  documents: BTreeMap<DocumentId, Document>,
+  next_id: DocumentId,
+  /// Undo depth for every document, including ones opened later.
+  history_depth: usize,
+}
+
+impl Default for Documents {
+  fn default() -> Self {
+    Self {
+      documents: BTreeMap::new(),
+      next_id: 1,
+      history_depth: history::DEFAULT_DEPTH,
+    }
+  }
+}
+
+impl Documents {
+  /// Opens a document showing `first`, which is also its first undo state.
+  pub fn create(&mut self, name: String, first: Edit) -> DocumentInfo {
+    let mut history = History::with_depth(self.history_depth);
+    history.push(first.snapshot);
+
+    let id = self.next_id;
+    self.next_id += 1;
+    let document = Document {
+      name,
+      image: first.image,
+      history,
+    };
+    let info = info(id, &document);
+    self.documents.insert(id, document);
+    info
+  }
+
+  /// Closes document `id`, freeing its memory, and returns how many bytes
+  /// that was.
+  pub fn close(&mut self, id: DocumentId) -> Result<usize, AppError> {
+    self
+      .documents
+      .remove(&id)
+      .map(|document| document.memory_usage())
+      .ok_or_else(|| not_found(id))
+  }
+
+  pub fn get(&self, id: DocumentId) -> Result<&Document, AppError> {
+    self.documents.get(&id).ok_or_else(|| not_found(id))
+  }
+
+  pub fn get_mut(&mut self, id: DocumentId) -> Result<&mut Document, AppError> {
+    self.documents.get_mut(&id).ok_or_else(|| not_found(id))
+  }
+
+  /// Applies `edit` to document `id` and returns its updated details.
+  pub fn edit(&mut self, id: DocumentId, edit: Edit) -> Result<DocumentInfo, AppError> {
+    let document = self.get_mut(id)?;
+    document.edit(edit);
+    Ok(info(id, document))
+  }
+
+  /// Every open document in the order they were created.
+  pub fn list(&self) -> Vec<DocumentInfo> {
+    self
+      .documents
+      .iter()
+      .map(|(&id, document)| info(id, document))
+      .collect()
+  }
+
+  /// Changes how many states every document keeps, dropping the oldest
+  /// if needed.
+  pub fn set_history_depth(&mut self, depth: usize) {
+    self.history_depth = depth;
+    for document in self.documents.values_mut() {
+      document.history.set_depth(depth);
+    }
+  }
+}
+
+fn info(id: DocumentId, document: &Document) -> DocumentInfo {
+  DocumentInfo {
+    id,
+    name: document.name.clone(),
+    width: document.image.width(),
+    height: document.image.height(),
+    memory_bytes: document.memory_usage(),
+    can_undo: document.history.can_undo(),
+    can_redo: document.history.can_redo(),
+  }
+}
+
+fn not_found(id: DocumentId) -> AppError {
+  AppError::NotFound(format!("no open document has id {}", id))
+}
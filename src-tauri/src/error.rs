@@ -0,0 +1,111 @@
+//! The error type returned by every command.
+//!
+//! It reaches the frontend as `{ kind, message }`, so the UI can switch on
+//! `kind` and only show `message` as detail. `TooLarge` also carries its
+//! `required` and `limit` fields.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppError {
+  /// A file or item that was asked for does not exist.
+  NotFound(String),
+  /// The data is in a format this build can't read or write.
+  UnsupportedFormat(String),
+  /// The data claims a supported format but is corrupt or truncated.
+  DecodeFailed(String),
+  /// The image is bigger than the pixel budget. `required` is the budget,
+  /// in pixels, that would let it load, for the UI to offer as an override.
+  TooLarge {
+    message: String,
+    required: u64,
+    limit: u64,
+  },
+  EncodeFailed(String),
+  /// Any other failure reading or writing the filesystem, clipboard, etc.
+  Io(String),
+  /// A parameter was out of range, unknown, or inconsistent with another.
+  InvalidArgument(String),
+  /// A feature this build leaves out or that its config doesn't set up.
+  NotConfigured(String),
+  /// A bug, such as a panic in a command. The log has the details.
+  Internal(String),
+}
+
+impl AppError {
+  /// The variant name, as sent to the frontend.
+  pub fn kind(&self) -> &'static str {
+    match self {
+      Self::NotFound(_) => "NotFound",
+      Self::UnsupportedFormat(_) => "UnsupportedFormat",
+      Self::DecodeFailed(_) => "DecodeFailed",
+      Self::TooLarge { .. } => "TooLarge",
+      Self::EncodeFailed(_) => "EncodeFailed",
+      Self::Io(_) => "Io",
+      Self::InvalidArgument(_) => "InvalidArgument",
+      Self::NotConfigured(_) => "NotConfigured",
+      Self::Internal(_) => "Internal",
+    }
+  }
+
+  pub fn message(&self) -> &str {
+    match self {
+      Self::NotFound(message)
+      | Self::UnsupportedFormat(message)
+      | Self::DecodeFailed(message)
+      | Self::EncodeFailed(message)
+      | Self::Io(message)
+      | Self::InvalidArgument(message)
+      | Self::NotConfigured(message)
+      | Self::Internal(message)
+      | Self::TooLarge { message, .. } => message,
+    }
+  }
+
+  /// A failure reading `path`, picking out missing files.
+  pub fn read(path: &Path, err: &io::Error) -> Self {
+    match err.kind() {
+      io::ErrorKind::NotFound => Self::NotFound(format!("file not found: {}", path.display())),
+      _ => Self::Io(format!("failed to read {}: {}", path.display(), err)),
+    }
+  }
+
+  /// A failure writing `path`.
+  pub fn write(path: &Path, err: &io::Error) -> Self {
+    Self::Io(format!("failed to write {}: {}", path.display(), err))
+  }
+}
+
+impl fmt::Display for AppError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.message())
+  }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let fields = if matches!(self, Self::TooLarge { .. }) {
+      4
+    } else {
+      2
+    };
+    let mut state = serializer.serialize_struct("AppError", fields)?;
+    state.serialize_field("kind", self.kind())?;
+    state.serialize_field("message", self.message())?;
+    if let Self::TooLarge {
+      required, limit, ..
+    } = self
+    {
+      state.serialize_field("required", required)?;
+      state.serialize_field("limit", limit)?;
+    }
+    state.end()
+  }
+}
@@ -0,0 +1,75 @@
+//! Files dragged onto a window.
+//!
+//! Drops are reported as `files-dropped` carrying only the paths we can
+//! open, with hover feedback through `drag-enter` and `drag-leave`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tauri::{FileDropEvent, Window};
+
+use crate::commands::is_supported_image;
+
+/// Payload of the `files-dropped` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct DroppedFiles {
+  pub paths: Vec<String>,
+  /// Dropped files, and files inside dropped folders, that aren't images
+  /// we support.
+  pub rejected: usize,
+}
+
+pub fn handle(window: &Window, event: &FileDropEvent) {
+  match event {
+    FileDropEvent::Hovered(_) => {
+      let _ = window.emit("drag-enter", ());
+    }
+    FileDropEvent::Dropped(paths) => {
+      let _ = window.emit("drag-leave", ());
+      let _ = window.emit("files-dropped", collect(paths));
+    }
+    FileDropEvent::Cancelled => {
+      let _ = window.emit("drag-leave", ());
+    }
+    _ => {}
+  }
+}
+
+/// Filters dropped paths down to supported images. Folders contribute the
+/// images directly inside them; their subfolders are not searched.
+fn collect(dropped: &[PathBuf]) -> DroppedFiles {
+  let mut files = DroppedFiles {
+    paths: Vec::new(),
+    rejected: 0,
+  };
+
+  for path in dropped {
+    if path.is_dir() {
+      let Ok(entries) = fs::read_dir(path) else {
+        continue;
+      };
+      let mut children: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|child| child.is_file())
+        .collect();
+      children.sort();
+      for child in children {
+        accept(&mut files, &child);
+      }
+    } else {
+      accept(&mut files, path);
+    }
+  }
+
+  files
+}
+
+fn accept(files: &mut DroppedFiles, path: &Path) {
+  if is_supported_image(path) {
+    files.paths.push(path.display().to_string());
+  } else {
+    files.rejected += 1;
+  }
+}
@@ -0,0 +1,97 @@
+//! HEIF/HEIC containers, as saved by iPhones.
+//!
+//! Decoding goes through libheif and is only built with the `heif` feature,
+//! but recognising the container is always available so builds without it
+//! can say why a file won't open.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+#[cfg(feature = "heif")]
+use crate::error::AppError;
+
+/// Major brands of HEIF files holding still images we can decode. AVIF
+/// shares the container but is left to the `image` crate.
+const BRANDS: [&[u8; 4]; 10] = [
+  b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"hevm", b"hevs", b"mif1", b"msf1",
+];
+
+/// Whether the file at `path` starts with a HEIF `ftyp` box.
+pub fn is_heif(path: &Path) -> bool {
+  let mut header = [0u8; 12];
+  File::open(path)
+    .and_then(|mut file| file.read_exact(&mut header))
+    .is_ok()
+    && &header[4..8] == b"ftyp"
+    && BRANDS.iter().any(|brand| header[8..12] == brand[..])
+}
+
+/// Decodes one image of the HEIF file at `path` to RGBA, along with its
+/// ICC profile if it has one.
+///
+/// `index` picks among the top-level images in file order; without it the
+/// primary image is used, which need not be the first. The container's
+/// rotation and mirroring are applied, so the result is upright. iPhones
+/// write those to match the EXIF orientation, and the HEIF spec has them
+/// take precedence, so EXIF orientation must not be applied again.
+///
+/// Images over `max_pixels` are rejected before they are decoded.
+#[cfg(feature = "heif")]
+pub fn decode(
+  path: &Path,
+  index: Option<usize>,
+  max_pixels: u64,
+) -> Result<(image::RgbaImage, Option<Vec<u8>>), AppError> {
+  use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+  let bytes = std::fs::read(path).map_err(|err| AppError::read(path, &err))?;
+  let decode_error = |err: libheif_rs::HeifError| {
+    AppError::DecodeFailed(format!("failed to decode {}: {}", path.display(), err))
+  };
+
+  let context = HeifContext::read_from_bytes(&bytes).map_err(decode_error)?;
+  let handle = match index {
+    None => context.primary_image_handle().map_err(decode_error)?,
+    Some(index) => {
+      let mut handles = context.top_level_image_handles();
+      if index >= handles.len() {
+        return Err(AppError::NotFound(format!(
+          "{} has {} images, no image at index {}",
+          path.display(),
+          handles.len(),
+          index
+        )));
+      }
+      handles.swap_remove(index)
+    }
+  };
+
+  crate::commands::check_pixels(path.display(), handle.width(), handle.height(), max_pixels)?;
+
+  let decoded = LibHeif::new()
+    .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)
+    .map_err(decode_error)?;
+  let plane = decoded.planes().interleaved.ok_or_else(|| {
+    AppError::DecodeFailed(format!(
+      "failed to decode {}: no RGBA plane",
+      path.display()
+    ))
+  })?;
+
+  // Rows may be padded, so copy them out one at a time.
+  let row_len = plane.width as usize * 4;
+  let mut pixels = Vec::with_capacity(row_len * plane.height as usize);
+  for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+    pixels.extend_from_slice(&row[..row_len]);
+  }
+  let image = image::RgbaImage::from_raw(plane.width, plane.height, pixels).ok_or_else(|| {
+    AppError::DecodeFailed(format!(
+      "failed to decode {}: truncated image data",
+      path.display()
+    ))
+  })?;
+
+  let icc_profile = handle.color_profile_raw().map(|profile| profile.data);
+  Ok((image, icc_profile))
+}
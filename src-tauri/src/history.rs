@@ -0,0 +1,191 @@
+//! Bounded undo/redo history of image states.
+//!
+//! States are kept PNG-compressed, which typically shrinks edited photos
+//! severalfold and screenshots far more, and are decoded only when undo or
+//! redo returns to them.
+
+use std::collections::VecDeque;
+
+use image::{ImageFormat, RgbaImage};
+
+use crate::error::AppError;
+use crate::png;
+
+pub const DEFAULT_DEPTH: usize = 20;
+
+/// An image state compressed for the history. Making one is the slow part
+/// of recording an edit, so it is separate from `History::push`.
+#[derive(Debug)]
+pub struct Snapshot(Vec<u8>);
+
+impl Snapshot {
+  pub fn new(image: &RgbaImage) -> Result<Self, AppError> {
+    png::encode_fast(image)
+      .map(Self)
+      .map_err(|err| AppError::EncodeFailed(format!("failed to store history state: {}", err)))
+  }
+}
+
+#[derive(Debug)]
+pub struct History {
+  depth: usize,
+  states: VecDeque<Vec<u8>>,
+  /// Index of the current state in `states`.
+  cursor: usize,
+}
+
+impl Default for History {
+  fn default() -> Self {
+    Self::with_depth(DEFAULT_DEPTH)
+  }
+}
+
+impl History {
+  /// A history keeping at most `depth` states, including the current one.
+  pub fn with_depth(depth: usize) -> Self {
+    Self {
+      depth: depth.max(1),
+      states: VecDeque::new(),
+      cursor: 0,
+    }
+  }
+
+  /// Records `snapshot` as the new current state, discarding anything
+  /// that could have been redone and, past the depth limit, the oldest
+  /// states.
+  pub fn push(&mut self, snapshot: Snapshot) {
+    if !self.states.is_empty() {
+      self.states.truncate(self.cursor + 1);
+    }
+    self.states.push_back(snapshot.0);
+    self.cursor = self.states.len() - 1;
+    self.trim();
+  }
+
+  /// Steps back one state and returns it.
+  pub fn undo(&mut self) -> Result<RgbaImage, AppError> {
+    if !self.can_undo() {
+      return Err(AppError::InvalidArgument("nothing to undo".into()));
+    }
+    let image = decode(&self.states[self.cursor - 1])?;
+    self.cursor -= 1;
+    Ok(image)
+  }
+
+  /// Steps forward one state and returns it.
+  pub fn redo(&mut self) -> Result<RgbaImage, AppError> {
+    if !self.can_redo() {
+      return Err(AppError::InvalidArgument("nothing to redo".into()));
+    }
+    let image = decode(&self.states[self.cursor + 1])?;
+    self.cursor += 1;
+    Ok(image)
+  }
+
+  pub fn can_undo(&self) -> bool {
+    self.cursor > 0
+  }
+
+  pub fn can_redo(&self) -> bool {
+    self.cursor + 1 < self.states.len()
+  }
+
+  /// Bytes held by the compressed states.
+  pub fn memory_usage(&self) -> usize {
+    self.states.iter().map(Vec::len).sum()
+  }
+
+  pub fn set_depth(&mut self, depth: usize) {
+    self.depth = depth.max(1);
+    self.trim();
+  }
+
+  /// Drops states beyond the depth limit, oldest first, then redo states
+  /// if the current state is itself the oldest kept.
+  fn trim(&mut self) {
+    while self.states.len() > self.depth && self.cursor > 0 {
+      self.states.pop_front();
+      self.cursor -= 1;
+    }
+    self.states.truncate(self.depth);
+  }
+}
+
+fn decode(encoded: &[u8]) -> Result<RgbaImage, AppError> {
+  image::load_from_memory_with_format(encoded, ImageFormat::Png)
+    .map(|image| image.to_rgba8())
+    .map_err(|err| AppError::DecodeFailed(format!("failed to restore history state: {}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use image::Rgba;
+
+  /// A one-pixel state told apart by its red channel.
+  fn state(n: u8) -> Snapshot {
+    Snapshot::new(&RgbaImage::from_pixel(1, 1, Rgba([n, 0, 0, 255]))).unwrap()
+  }
+
+  fn red(image: RgbaImage) -> u8 {
+    image.get_pixel(0, 0)[0]
+  }
+
+  fn history(depth: usize, states: std::ops::RangeInclusive<u8>) -> History {
+    let mut history = History::with_depth(depth);
+    for n in states {
+      history.push(state(n));
+    }
+    history
+  }
+
+  #[test]
+  fn undo_at_the_start_and_redo_at_the_end_fail() {
+    let mut history = history(5, 1..=1);
+    assert!(matches!(history.undo(), Err(AppError::InvalidArgument(_))));
+    assert!(matches!(history.redo(), Err(AppError::InvalidArgument(_))));
+  }
+
+  #[test]
+  fn the_oldest_states_are_dropped_past_the_depth() {
+    let mut history = history(3, 1..=5);
+    assert_eq!(red(history.undo().unwrap()), 4);
+    assert_eq!(red(history.undo().unwrap()), 3);
+    assert!(!history.can_undo());
+    assert_eq!(red(history.redo().unwrap()), 4);
+  }
+
+  #[test]
+  fn depth_one_keeps_the_newest_state() {
+    let history = history(1, 1..=2);
+    assert!(!history.can_undo() && !history.can_redo());
+    assert_eq!(red(decode(&history.states[0]).unwrap()), 2);
+  }
+
+  #[test]
+  fn shrinking_the_depth_keeps_the_newest_states() {
+    let mut history = history(5, 1..=5);
+    history.set_depth(2);
+    assert_eq!(red(history.undo().unwrap()), 4);
+    assert!(!history.can_undo());
+  }
+
+  #[test]
+  fn pushing_after_undo_drops_the_redo_states() {
+    let mut history = history(5, 1..=3);
+    history.undo().unwrap();
+    history.undo().unwrap();
+    history.push(state(4));
+    assert!(!history.can_redo());
+    assert_eq!(red(history.undo().unwrap()), 1);
+  }
+
+  #[test]
+  fn a_failed_undo_leaves_the_cursor_alone() {
+    let mut history = history(5, 1..=2);
+    history.states[0] = b"not a png".to_vec();
+    assert!(matches!(history.undo(), Err(AppError::DecodeFailed(_))));
+    assert_eq!(history.cursor, 1);
+  }
+}
@@ -0,0 +1,124 @@
+//! Marker-level JPEG helpers that work without decoding the image.
+
+/// Tag number of the EXIF orientation field.
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// Marker segments before the start of scan, as `(marker, start, end)`
+/// where `start..end` covers the whole segment including its header.
+pub fn segments(jpeg: &[u8]) -> Option<Vec<(u8, usize, usize)>> {
+  if jpeg.get(..2)? != [0xFF, 0xD8] {
+    return None;
+  }
+
+  let mut segments = Vec::new();
+  let mut pos = 2;
+  loop {
+    if *jpeg.get(pos)? != 0xFF {
+      return None;
+    }
+    let marker = *jpeg.get(pos + 1)?;
+    match marker {
+      // Fill byte before the real marker.
+      0xFF => pos += 1,
+      // Start of scan or end of image: entropy-coded data follows.
+      0xDA | 0xD9 => return Some(segments),
+      // Standalone markers carry no length.
+      0x01 | 0xD0..=0xD7 => pos += 2,
+      _ => {
+        let len = u16::from_be_bytes([*jpeg.get(pos + 2)?, *jpeg.get(pos + 3)?]) as usize;
+        let end = pos + 2 + len;
+        if len < 2 || end > jpeg.len() {
+          return None;
+        }
+        segments.push((marker, pos, end));
+        pos = end;
+      }
+    }
+  }
+}
+
+/// Copies a JPEG, leaving out every segment whose marker is in `markers`.
+/// The entropy-coded image data is copied byte for byte.
+pub fn without_segments(jpeg: &[u8], markers: &[u8]) -> Option<Vec<u8>> {
+  let segments = segments(jpeg)?;
+  let scan_start = segments.last().map_or(2, |&(_, _, end)| end);
+
+  let mut out = Vec::with_capacity(jpeg.len());
+  out.extend_from_slice(&jpeg[..2]);
+  for (marker, start, end) in segments {
+    if !markers.contains(&marker) {
+      out.extend_from_slice(&jpeg[start..end]);
+    }
+  }
+  out.extend_from_slice(&jpeg[scan_start..]);
+  Some(out)
+}
+
+/// Reads the orientation tag from the EXIF block of a JPEG, if present.
+pub fn exif_orientation(jpeg: &[u8]) -> Option<u16> {
+  let (offset, big_endian) = find_orientation(jpeg)?;
+  let bytes = [jpeg[offset], jpeg[offset + 1]];
+  Some(if big_endian {
+    u16::from_be_bytes(bytes)
+  } else {
+    u16::from_le_bytes(bytes)
+  })
+}
+
+/// Overwrites the EXIF orientation tag in place. Returns `false` if the
+/// JPEG has no orientation tag to update.
+pub fn set_exif_orientation(jpeg: &mut [u8], value: u16) -> bool {
+  let Some((offset, big_endian)) = find_orientation(jpeg) else {
+    return false;
+  };
+  let bytes = if big_endian {
+    value.to_be_bytes()
+  } else {
+    value.to_le_bytes()
+  };
+  jpeg[offset..offset + 2].copy_from_slice(&bytes);
+  true
+}
+
+/// Locates the value of the orientation entry in IFD0, returning its byte
+/// offset in `jpeg` and whether the TIFF data is big-endian.
+fn find_orientation(jpeg: &[u8]) -> Option<(usize, bool)> {
+  let (_, start, end) = segments(jpeg)?.into_iter().find(|&(marker, start, end)| {
+    marker == 0xE1 && jpeg[start + 4..end].starts_with(b"Exif\0\0")
+  })?;
+
+  let tiff_start = start + 10;
+  let tiff = &jpeg[tiff_start..end];
+  let big_endian = match tiff.get(..2)? {
+    b"MM" => true,
+    b"II" => false,
+    _ => return None,
+  };
+  let read_u16 = |at: usize| -> Option<u16> {
+    let bytes = [*tiff.get(at)?, *tiff.get(at + 1)?];
+    Some(if big_endian {
+      u16::from_be_bytes(bytes)
+    } else {
+      u16::from_le_bytes(bytes)
+    })
+  };
+  let read_u32 = |at: usize| -> Option<u32> {
+    let bytes: [u8; 4] = tiff.get(at..at + 4)?.try_into().ok()?;
+    Some(if big_endian {
+      u32::from_be_bytes(bytes)
+    } else {
+      u32::from_le_bytes(bytes)
+    })
+  };
+
+  let ifd = read_u32(4)? as usize;
+  let count = read_u16(ifd)? as usize;
+  for index in 0..count {
+    let entry = ifd + 2 + index * 12;
+    // The value of a single SHORT sits in the first two bytes of the field.
+    if read_u16(entry)? == ORIENTATION_TAG && read_u16(entry + 2)? == 3 {
+      return Some((tiff_start + entry + 8, big_endian));
+    }
+  }
+  None
+}
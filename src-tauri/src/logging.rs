@@ -0,0 +1,138 @@
+//! The app's log file, and the span every command runs in.
+//!
+//! Logs go to the app log directory, one file per day, keeping the last
+//! week. Records from the `log` crate, such as Tauri's own, end up in the
+//! same file.
+
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use futures_util::FutureExt;
+use tracing::level_filters::LevelFilter;
+use tracing::Instrument;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, Registry};
+
+use crate::error::AppError;
+
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::INFO;
+const MAX_LOG_FILES: usize = 7;
+
+/// Where the log is written and a handle for changing its level at runtime.
+pub struct Logging {
+  dir: Option<PathBuf>,
+  level: reload::Handle<LevelFilter, Registry>,
+}
+
+impl Logging {
+  /// The directory holding the log files, or `None` if it couldn't be
+  /// opened and the log goes to stderr instead.
+  pub fn dir(&self) -> Option<&Path> {
+    self.dir.as_deref()
+  }
+
+  pub fn set_level(&self, level: LevelFilter) -> Result<(), AppError> {
+    self
+      .level
+      .reload(level)
+      .map_err(|err| AppError::Io(format!("failed to change the log level: {}", err)))
+  }
+}
+
+/// Starts logging to daily files in `dir` and logs panics from then on.
+///
+/// A log that can't be opened is written to stderr instead; the app is
+/// still usable without one.
+pub fn init(dir: Option<PathBuf>) -> Logging {
+  let mut open_error = None;
+  let appender = dir.as_deref().and_then(|dir| {
+    RollingFileAppender::builder()
+      .rotation(Rotation::DAILY)
+      .filename_prefix("image-pro")
+      .filename_suffix("log")
+      .max_log_files(MAX_LOG_FILES)
+      .build(dir)
+      .map_err(|err| {
+        open_error = Some(format!(
+          "failed to open the log in {}: {}",
+          dir.display(),
+          err
+        ))
+      })
+      .ok()
+  });
+  let dir = dir.filter(|_| appender.is_some());
+
+  let (filter, level) = reload::Layer::new(DEFAULT_LEVEL);
+  let file = appender.map(|appender| {
+    fmt::layer()
+      .with_writer(appender)
+      .with_ansi(false)
+      // One line per command as it finishes, with how long it took.
+      .with_span_events(FmtSpan::CLOSE)
+  });
+  let stderr = file
+    .is_none()
+    .then(|| fmt::layer().with_writer(std::io::stderr));
+  let _ = tracing_subscriber::registry()
+    .with(filter)
+    .with(file)
+    .with(stderr)
+    .try_init();
+  if let Some(err) = open_error {
+    tracing::warn!("{}", err);
+  }
+
+  let default_hook = panic::take_hook();
+  panic::set_hook(Box::new(move |info| {
+    // Logged inside whatever span panicked, so the command is named.
+    tracing::error!("{}\n{}", info, std::backtrace::Backtrace::force_capture());
+    default_hook(info);
+  }));
+
+  Logging { dir, level }
+}
+
+/// Runs the body of the command `name` in a span, logging its duration and
+/// any error it returns.
+///
+/// A panic is turned into an `Internal` error, so the frontend's call
+/// fails instead of never settling.
+pub async fn traced<T, F>(name: &'static str, body: F) -> Result<T, AppError>
+where
+  F: Future<Output = Result<T, AppError>>,
+{
+  let span = tracing::info_span!("command", name);
+  let result = AssertUnwindSafe(body.instrument(span.clone()))
+    .catch_unwind()
+    .await;
+  span.in_scope(|| finish(name, result))
+}
+
+/// `traced` for synchronous commands, which can't fail other than by
+/// panicking.
+pub fn traced_sync<T>(name: &'static str, body: impl FnOnce() -> T) -> Result<T, AppError> {
+  let span = tracing::info_span!("command", name);
+  span.in_scope(|| finish(name, panic::catch_unwind(AssertUnwindSafe(|| Ok(body())))))
+}
+
+fn finish<T>(
+  name: &str,
+  result: Result<Result<T, AppError>, Box<dyn std::any::Any + Send>>,
+) -> Result<T, AppError> {
+  match result {
+    Ok(Err(err)) => {
+      tracing::warn!(kind = err.kind(), "{}", err);
+      Err(err)
+    }
+    Ok(result) => result,
+    // The panic hook has already logged the details.
+    Err(_) => Err(AppError::Internal(format!(
+      "{} crashed; the log has the details",
+      name
+    ))),
+  }
+}
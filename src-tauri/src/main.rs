@@ -3,18 +3,186 @@
   windows_subsystem = "windows"
 )]
 
-use tauri::{Manager, WindowBuilder, WindowUrl};
+mod commands;
+mod documents;
+mod error;
+mod file_drop;
+mod heif;
+mod history;
+mod jpeg;
+mod logging;
+mod menu;
+mod open_files;
+mod orientation;
+mod png;
+mod preview;
+mod riff;
+mod screenshot;
+mod single_instance;
+mod sync;
+mod thumbnail_cache;
+mod tile_cache;
+mod tray;
+mod updater;
+mod window_state;
+
+use std::sync::Mutex;
+
+use tauri::{Manager, WindowBuilder, WindowEvent, WindowUrl};
 
 fn main() {
+  let context = tauri::generate_context!();
+  let logging = logging::init(tauri::api::path::app_log_dir(context.config()));
+  tracing::info!("starting Image Pro {}", env!("CARGO_PKG_VERSION"));
+
+  let listener = match single_instance::claim(&context.config().tauri.bundle.identifier) {
+    single_instance::Instance::Primary(listener) => listener,
+    single_instance::Instance::Secondary => {
+      tracing::info!("handed the launch to the running instance");
+      return;
+    }
+  };
+
   tauri::Builder::default()
-    .setup(|app| {
-      let window = WindowBuilder::new(app, "main", WindowUrl::default())
+    .setup(move |app| {
+      let saved = window_state::load(&app.handle());
+      let (width, height) = saved.as_ref().map_or(
+        (window_state::DEFAULT_WIDTH, window_state::DEFAULT_HEIGHT),
+        |state| (state.width, state.height),
+      );
+
+      // tauri.conf.json declares no windows: the main one is built here so it
+      // opens at the saved geometry instead of clashing with a config window.
+      let mut builder = WindowBuilder::new(app, "main", WindowUrl::default())
         .title("Image Pro")
-        .inner_size(1200.0, 800.0)
+        .inner_size(width, height)
         .resizable(true)
-        .build()?;
+        .visible(false);
+      if let Some(state) = &saved {
+        builder = builder
+          .position(state.x, state.y)
+          .maximized(state.maximized);
+      }
+      let window = builder.build()?;
+      window_state::ensure_visible(&window)?;
+      window.show()?;
+
+      let cache_dir = app
+        .path_resolver()
+        .app_data_dir()
+        .ok_or("could not resolve the app data directory")?
+        .join("thumbnails");
+      app.manage(thumbnail_cache::ThumbnailCache::new(cache_dir));
+
+      let launch_files = open_files::paths_from_args(
+        &std::env::current_dir().unwrap_or_default(),
+        std::env::args().skip(1),
+      );
+      app
+        .state::<open_files::OpenFiles>()
+        .open(&app.handle(), launch_files);
+      if let Some(listener) = listener {
+        single_instance::listen(app.handle(), listener);
+      }
+      updater::check(app.handle());
+
+      let shortcut = screenshot::CaptureShortcut::default();
+      if let Err(err) = screenshot::register(&app.handle(), &shortcut, screenshot::DEFAULT_SHORTCUT)
+      {
+        tracing::warn!("{}", err);
+      }
+      app.manage(shortcut);
+
       Ok(())
     })
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
-}
\ No newline at end of file
+    .menu(menu::build())
+    .on_menu_event(menu::handle_event)
+    .system_tray(tray::build())
+    .on_system_tray_event(tray::handle_event)
+    .register_uri_scheme_protocol(preview::SCHEME, preview::handle_request)
+    .on_window_event(|event| match event.event() {
+      WindowEvent::CloseRequested { .. } if event.window().label() == "main" => {
+        if let Err(err) = window_state::save(event.window()) {
+          tracing::warn!("failed to save window state: {}", err);
+        }
+      }
+      WindowEvent::FileDrop(drop) => file_drop::handle(event.window(), drop),
+      _ => {}
+    })
+    .on_page_load(|window, _| {
+      if window.label() == "main" {
+        window.state::<open_files::OpenFiles>().mark_ready(&window);
+      }
+    })
+    .manage(logging)
+    .manage(commands::BatchCancel::default())
+    .manage(open_files::OpenFiles::default())
+    .manage(updater::PendingUpdate::default())
+    .manage(Mutex::new(documents::Documents::default()))
+    .manage(Mutex::new(preview::PreviewStore::default()))
+    .manage(Mutex::new(tile_cache::TileCache::default()))
+    .invoke_handler(tauri::generate_handler![
+      commands::open_image,
+      commands::save_image,
+      commands::image_dimensions,
+      commands::image_levels,
+      commands::load_tile,
+      commands::set_max_pixels,
+      commands::rasterize_svg,
+      commands::new_canvas,
+      commands::capture_screen,
+      commands::set_capture_shortcut,
+      commands::batch_convert,
+      commands::cancel_batch,
+      commands::resize_image,
+      commands::resize_to_bounds,
+      commands::crop_image,
+      commands::trim_borders,
+      commands::flip_image,
+      commands::rotate_image,
+      commands::auto_orient,
+      commands::perspective_correct,
+      commands::rotate_jpeg_lossless,
+      commands::read_exif,
+      commands::strip_metadata,
+      commands::compute_histogram,
+      commands::compare_images,
+      commands::adjust_image,
+      commands::to_grayscale,
+      commands::posterize,
+      commands::quantize,
+      commands::extract_palette,
+      commands::average_color,
+      commands::convert_to_srgb,
+      commands::gaussian_blur,
+      commands::unsharp_mask,
+      commands::make_thumbnail,
+      commands::clear_thumbnail_cache,
+      commands::paste_image_from_clipboard,
+      commands::copy_image_to_clipboard,
+      commands::open_document,
+      commands::create_document,
+      commands::close_document,
+      commands::list_documents,
+      commands::document_image,
+      commands::push_history,
+      commands::undo,
+      commands::redo,
+      commands::set_history_depth,
+      commands::apply_watermark,
+      commands::remove_alpha,
+      commands::add_alpha_from_color,
+      commands::watermark_directory,
+      commands::images_to_pdf,
+      commands::create_gif,
+      commands::export_ico,
+      commands::preview_file,
+      commands::release_preview,
+      commands::get_log_path,
+      commands::set_log_level,
+      commands::install_update,
+    ])
+    .build(context)
+    .expect("error while building tauri application")
+    .run(updater::handle_event);
+}
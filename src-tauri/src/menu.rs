@@ -0,0 +1,80 @@
+//! The native application menu.
+//!
+//! Items other than Quit are forwarded to the frontend as `menu://<id>`
+//! events, e.g. `menu://save-as`, so the UI decides what they do.
+
+#[cfg(target_os = "macos")]
+use tauri::AboutMetadata;
+use tauri::{CustomMenuItem, Manager, Menu, MenuItem, Submenu, WindowMenuEvent};
+
+use crate::tray;
+
+#[cfg(target_os = "macos")]
+const APP_NAME: &str = "Image Pro";
+const QUIT: &str = "quit";
+
+pub fn build() -> Menu {
+  let file = Menu::new()
+    .add_item(CustomMenuItem::new("open", "Open…").accelerator("CmdOrCtrl+O"))
+    .add_item(CustomMenuItem::new("save", "Save").accelerator("CmdOrCtrl+S"))
+    .add_item(CustomMenuItem::new("save-as", "Save As…").accelerator("CmdOrCtrl+Shift+S"));
+  // macOS keeps Quit in the app menu instead.
+  #[cfg(not(target_os = "macos"))]
+  let file = file
+    .add_native_item(MenuItem::Separator)
+    .add_item(quit_item("Quit"));
+
+  let edit = Menu::new()
+    .add_item(CustomMenuItem::new("undo", "Undo").accelerator("CmdOrCtrl+Z"))
+    .add_item(CustomMenuItem::new("redo", "Redo").accelerator("CmdOrCtrl+Shift+Z"));
+  // Clipboard shortcuts in text fields only work with these present, and
+  // GTK doesn't support them.
+  #[cfg(not(target_os = "linux"))]
+  let edit = edit
+    .add_native_item(MenuItem::Separator)
+    .add_native_item(MenuItem::Cut)
+    .add_native_item(MenuItem::Copy)
+    .add_native_item(MenuItem::Paste)
+    .add_native_item(MenuItem::SelectAll);
+
+  let view = Menu::new()
+    .add_item(CustomMenuItem::new("zoom-in", "Zoom In").accelerator("CmdOrCtrl+Plus"))
+    .add_item(CustomMenuItem::new("zoom-out", "Zoom Out").accelerator("CmdOrCtrl+Minus"))
+    .add_item(CustomMenuItem::new("fit", "Fit to Window").accelerator("CmdOrCtrl+0"));
+
+  let menu = Menu::new();
+  #[cfg(target_os = "macos")]
+  let menu = menu.add_submenu(Submenu::new(
+    APP_NAME,
+    Menu::new()
+      .add_native_item(MenuItem::About(
+        APP_NAME.to_string(),
+        AboutMetadata::default(),
+      ))
+      .add_native_item(MenuItem::Separator)
+      .add_native_item(MenuItem::Hide)
+      .add_native_item(MenuItem::HideOthers)
+      .add_native_item(MenuItem::ShowAll)
+      .add_native_item(MenuItem::Separator)
+      .add_item(quit_item(&format!("Quit {}", APP_NAME))),
+  ));
+
+  menu
+    .add_submenu(Submenu::new("File", file))
+    .add_submenu(Submenu::new("Edit", edit))
+    .add_submenu(Submenu::new("View", view))
+}
+
+fn quit_item(title: &str) -> CustomMenuItem {
+  CustomMenuItem::new(QUIT, title).accelerator("CmdOrCtrl+Q")
+}
+
+/// Handles a click on one of our menu items.
+pub fn handle_event(event: WindowMenuEvent) {
+  let id = event.menu_item_id();
+  if id == QUIT {
+    tray::quit(&event.window().app_handle());
+    return;
+  }
+  let _ = event.window().emit(&format!("menu://{}", id), ());
+}
@@ -0,0 +1,78 @@
+//! Files handed to the app by the OS, e.g. through "Open With".
+//!
+//! Paths that arrive before the frontend has loaded are queued and
+//! delivered as `open-file` events, one per file, once the main window
+//! finishes loading. Files a second launch was given arrive here too,
+//! through `single_instance`.
+//!
+//! macOS delivers dock drops and Finder opens as Apple events rather than
+//! arguments; Tauri 1 doesn't surface those, so only launch arguments are
+//! handled there for now.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Manager, Window};
+
+use crate::sync;
+
+#[derive(Debug, Default)]
+pub struct OpenFiles {
+  inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+  ready: bool,
+  pending: Vec<String>,
+}
+
+impl OpenFiles {
+  /// Delivers `paths` to the main window, or queues them if it hasn't
+  /// loaded yet.
+  pub fn open(&self, app: &AppHandle, paths: Vec<String>) {
+    let mut inner = self.lock();
+    if !inner.ready {
+      inner.pending.extend(paths);
+      return;
+    }
+    drop(inner);
+
+    if let Some(window) = app.get_window("main") {
+      emit(&window, paths);
+    }
+  }
+
+  /// Marks the main window as loaded and flushes anything queued.
+  pub fn mark_ready(&self, window: &Window) {
+    let pending = {
+      let mut inner = self.lock();
+      inner.ready = true;
+      std::mem::take(&mut inner.pending)
+    };
+    emit(window, pending);
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+    sync::lock(&self.inner)
+  }
+}
+
+/// Picks the file paths out of command-line arguments, skipping flags and
+/// anything that isn't an existing file. Relative paths are taken from
+/// `cwd`, the working directory of the process that got the arguments.
+pub fn paths_from_args(cwd: &Path, args: impl IntoIterator<Item = String>) -> Vec<String> {
+  args
+    .into_iter()
+    .filter(|arg| !arg.starts_with('-'))
+    .map(|arg| cwd.join(arg))
+    .filter(|path| path.is_file())
+    .map(|path| path.display().to_string())
+    .collect()
+}
+
+fn emit(window: &Window, paths: Vec<String>) {
+  for path in paths {
+    let _ = window.emit("open-file", path);
+  }
+}
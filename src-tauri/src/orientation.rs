@@ -0,0 +1,119 @@
+//! The eight EXIF orientations and how they compose.
+
+use image::{imageops, RgbaImage};
+
+/// A rotation/mirror from the dihedral group of the rectangle.
+///
+/// Stored as an optional horizontal flip applied first, followed by
+/// `quarter_turns` clockwise quarter turns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Orientation {
+  flip: bool,
+  quarter_turns: u8,
+}
+
+impl Orientation {
+  pub const IDENTITY: Self = Self::new(false, 0);
+
+  const fn new(flip: bool, quarter_turns: u8) -> Self {
+    Self {
+      flip,
+      quarter_turns: quarter_turns % 4,
+    }
+  }
+
+  /// The transform that takes stored pixels to their displayed position for
+  /// an EXIF orientation tag, or `None` for values outside 1-8.
+  pub fn from_exif(tag: u16) -> Option<Self> {
+    Some(match tag {
+      1 => Self::new(false, 0),
+      2 => Self::new(true, 0),
+      3 => Self::new(false, 2),
+      4 => Self::new(true, 2),
+      5 => Self::new(true, 3),
+      6 => Self::new(false, 1),
+      7 => Self::new(true, 1),
+      8 => Self::new(false, 3),
+      _ => return None,
+    })
+  }
+
+  /// A clockwise rotation by a multiple of 90 degrees.
+  pub fn rotation(quarter_turns: u8) -> Self {
+    Self::new(false, quarter_turns)
+  }
+
+  /// The transform equivalent to applying `self` and then `next`.
+  pub fn then(self, next: Self) -> Self {
+    // A flip followed by clockwise turns equals counter-clockwise turns
+    // followed by the flip, so `next`'s flip reverses our rotation.
+    let turns = if next.flip {
+      4 - self.quarter_turns
+    } else {
+      self.quarter_turns
+    };
+    Self::new(self.flip != next.flip, turns + next.quarter_turns)
+  }
+
+  /// Transforms `image` into this orientation.
+  pub fn apply(self, image: RgbaImage) -> RgbaImage {
+    let image = if self.flip {
+      imageops::flip_horizontal(&image)
+    } else {
+      image
+    };
+    match self.quarter_turns {
+      1 => imageops::rotate90(&image),
+      2 => imageops::rotate180(&image),
+      3 => imageops::rotate270(&image),
+      _ => image,
+    }
+  }
+
+  pub fn is_flipped(self) -> bool {
+    self.flip
+  }
+
+  pub fn quarter_turns(self) -> u8 {
+    self.quarter_turns
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use image::Rgba;
+
+  #[test]
+  fn every_exif_tag_moves_pixels_where_the_spec_says() {
+    let (w, h) = (3u32, 2u32);
+    let stored = RgbaImage::from_fn(w, h, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+
+    // Where the stored pixel (x, y) ends up on screen, per the EXIF spec.
+    let position = |tag, x, y| match tag {
+      1 => (x, y),
+      2 => (w - 1 - x, y),
+      3 => (w - 1 - x, h - 1 - y),
+      4 => (x, h - 1 - y),
+      5 => (y, x),
+      6 => (h - 1 - y, x),
+      7 => (h - 1 - y, w - 1 - x),
+      _ => (y, w - 1 - x),
+    };
+    for tag in 1..=8 {
+      let upright = Orientation::from_exif(tag).unwrap().apply(stored.clone());
+      for (x, y, pixel) in stored.enumerate_pixels() {
+        let (dx, dy) = position(tag, x, y);
+        assert_eq!(
+          upright.get_pixel(dx, dy),
+          pixel,
+          "tag {} at ({}, {})",
+          tag,
+          x,
+          y
+        );
+      }
+    }
+  }
+}
@@ -0,0 +1,48 @@
+//! PNG helpers: fast encoding, and chunk-level edits that work without
+//! decoding the image.
+
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{ImageEncoder, ImageResult, RgbaImage};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Copies a PNG up to and including IEND, leaving out every chunk whose
+/// type is in `types`. Every other chunk, including the image data, is
+/// copied byte for byte.
+pub fn without_chunks(png: &[u8], types: &[&[u8; 4]]) -> Option<Vec<u8>> {
+  if png.get(..8)? != SIGNATURE {
+    return None;
+  }
+
+  let mut out = Vec::with_capacity(png.len());
+  out.extend_from_slice(&SIGNATURE);
+  let mut pos = 8;
+  loop {
+    let len = u32::from_be_bytes(png.get(pos..pos + 4)?.try_into().ok()?) as usize;
+    // Length, type and CRC around the data.
+    let end = pos.checked_add(len)?.checked_add(12)?;
+    let chunk = png.get(pos..end)?;
+    let kind = &chunk[4..8];
+    if !types.iter().any(|&skipped| skipped == kind) {
+      out.extend_from_slice(chunk);
+    }
+    if kind == b"IEND" {
+      return Some(out);
+    }
+    pos = end;
+  }
+}
+
+/// Encodes `image` favouring speed over size, for PNGs the app keeps to
+/// itself such as previews and undo states.
+pub fn encode_fast(image: &RgbaImage) -> ImageResult<Vec<u8>> {
+  let mut encoded = Vec::new();
+  PngEncoder::new_with_quality(&mut encoded, CompressionType::Fast, FilterType::Adaptive)
+    .write_image(
+      image.as_raw(),
+      image.width(),
+      image.height(),
+      image::ExtendedColorType::Rgba8,
+    )?;
+  Ok(encoded)
+}
@@ -0,0 +1,112 @@
+//! Encoded images served to the webview over the `imgpro://` protocol.
+//!
+//! Handing `<img>` tags a URL avoids pushing multi-megabyte buffers through
+//! `invoke`. The store only keeps the most recent previews, so ids the
+//! frontend forgets to release are evicted as new ones arrive.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::Mutex;
+
+use tauri::http::{Request, Response, ResponseBuilder};
+use tauri::{AppHandle, Manager};
+
+use crate::sync;
+
+pub const SCHEME: &str = "imgpro";
+/// Most previews kept at once.
+pub const MAX_ENTRIES: usize = 32;
+/// Most encoded bytes kept at once. The newest preview is always kept, even
+/// if it alone is bigger.
+pub const MAX_BYTES: usize = 256 * 1024 * 1024;
+
+#[derive(Debug)]
+struct Entry {
+  id: u64,
+  mime: &'static str,
+  data: Vec<u8>,
+}
+
+/// Previews by id, least recently used first.
+#[derive(Debug, Default)]
+pub struct PreviewStore {
+  entries: VecDeque<Entry>,
+  bytes: usize,
+  next_id: u64,
+}
+
+impl PreviewStore {
+  /// Stores an encoded image and returns its id, evicting the least
+  /// recently used previews past the limits.
+  pub fn insert(&mut self, mime: &'static str, data: Vec<u8>) -> u64 {
+    let id = self.next_id;
+    self.next_id += 1;
+    self.bytes += data.len();
+    self.entries.push_back(Entry { id, mime, data });
+
+    while self.entries.len() > 1 && (self.entries.len() > MAX_ENTRIES || self.bytes > MAX_BYTES) {
+      if let Some(evicted) = self.entries.pop_front() {
+        self.bytes -= evicted.data.len();
+      }
+    }
+    id
+  }
+
+  /// The MIME type and bytes of preview `id`, marking it recently used.
+  pub fn get(&mut self, id: u64) -> Option<(&'static str, Vec<u8>)> {
+    let index = self.entries.iter().position(|entry| entry.id == id)?;
+    let entry = self.entries.remove(index)?;
+    let found = (entry.mime, entry.data.clone());
+    self.entries.push_back(entry);
+    Some(found)
+  }
+
+  /// Drops preview `id`, returning whether it was still stored.
+  pub fn remove(&mut self, id: u64) -> bool {
+    match self.entries.iter().position(|entry| entry.id == id) {
+      Some(index) => {
+        if let Some(entry) = self.entries.remove(index) {
+          self.bytes -= entry.data.len();
+        }
+        true
+      }
+      None => false,
+    }
+  }
+}
+
+/// The URL the webview loads preview `id` from. WebView2 on Windows only
+/// serves custom schemes through `https://<scheme>.localhost`.
+pub fn url(id: u64) -> String {
+  if cfg!(windows) {
+    format!("https://{}.localhost/preview/{}", SCHEME, id)
+  } else {
+    format!("{}://preview/{}", SCHEME, id)
+  }
+}
+
+/// Answers `imgpro://preview/<id>` requests from the store, or 404 for
+/// ids that were released or evicted.
+pub fn handle_request(app: &AppHandle, request: &Request) -> Result<Response, Box<dyn Error>> {
+  let found =
+    parse_id(request.uri()).and_then(|id| sync::lock(&app.state::<Mutex<PreviewStore>>()).get(id));
+
+  let response = match found {
+    Some((mime, data)) => ResponseBuilder::new()
+      .mimetype(mime)
+      // The store already holds the bytes; no need for the webview to
+      // cache a second copy.
+      .header("Cache-Control", "no-store")
+      .header("Access-Control-Allow-Origin", "*")
+      .body(data)?,
+    None => ResponseBuilder::new().status(404).body(Vec::new())?,
+  };
+  Ok(response)
+}
+
+/// The id in `.../preview/<id>`, ignoring any query or fragment.
+fn parse_id(uri: &str) -> Option<u64> {
+  let (_, rest) = uri.split_once("preview/")?;
+  let end = rest.find(['?', '#', '/']).unwrap_or(rest.len());
+  rest[..end].parse().ok()
+}
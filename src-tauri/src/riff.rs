@@ -0,0 +1,47 @@
+//! Chunk-level WebP helpers that work without decoding the image.
+
+/// VP8X flag bits announcing an ICC profile, EXIF and XMP chunk.
+const FLAGS: [(&[u8; 4], u8); 3] = [(b"ICCP", 0x20), (b"EXIF", 0x08), (b"XMP ", 0x04)];
+
+/// Copies a WebP, leaving out every chunk whose FourCC is in `fourccs`.
+///
+/// The RIFF size is rewritten to match, and the VP8X header stops
+/// announcing ICC, EXIF or XMP data that was left out. Every other chunk,
+/// including the image data, is copied byte for byte.
+pub fn webp_without_chunks(webp: &[u8], fourccs: &[&[u8; 4]]) -> Option<Vec<u8>> {
+  if webp.get(..4)? != b"RIFF" || webp.get(8..12)? != b"WEBP" {
+    return None;
+  }
+  let riff_end = (u32::from_le_bytes(webp.get(4..8)?.try_into().ok()?) as usize).checked_add(8)?;
+  let body = webp.get(..riff_end)?;
+
+  let mut out = Vec::with_capacity(body.len());
+  out.extend_from_slice(&body[..12]);
+  let mut vp8x_flags = None;
+  let mut pos = 12;
+  while pos < body.len() {
+    let fourcc = body.get(pos..pos + 4)?;
+    let len = u32::from_le_bytes(body.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+    // Chunks are padded to an even length.
+    let end = pos.checked_add(8)?.checked_add(len)?.checked_add(len % 2)?;
+    let chunk = body.get(pos..end)?;
+    if !fourccs.iter().any(|&skipped| skipped == fourcc) {
+      if fourcc == b"VP8X" {
+        vp8x_flags = Some(out.len() + 8);
+      }
+      out.extend_from_slice(chunk);
+    }
+    pos = end;
+  }
+
+  if let Some(at) = vp8x_flags {
+    for (fourcc, flag) in FLAGS {
+      if fourccs.contains(&fourcc) {
+        *out.get_mut(at)? &= !flag;
+      }
+    }
+  }
+  let riff_size = u32::try_from(out.len() - 8).ok()?;
+  out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+  Some(out)
+}
@@ -0,0 +1,85 @@
+//! The global shortcut that screenshots the primary monitor into the
+//! editor.
+//!
+//! The capture arrives in the main window as `screenshot-captured`
+//! carrying the image, or `screenshot-failed` carrying the error.
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use tauri::{AppHandle, GlobalShortcutManager, Manager};
+
+use crate::commands::{capture_monitor, ImageData};
+use crate::error::AppError;
+use crate::sync;
+
+pub const DEFAULT_SHORTCUT: &str = "CmdOrCtrl+Shift+4";
+
+/// How long to wait after hiding the main window before capturing, so it
+/// is gone from the screen.
+const HIDE_DELAY: Duration = Duration::from_millis(200);
+
+/// The accelerator currently registered, held in managed state.
+#[derive(Debug, Default)]
+pub struct CaptureShortcut(Mutex<Option<String>>);
+
+/// Makes `accelerator` the capture shortcut, replacing the previous one.
+/// The previous one stays registered if `accelerator` can't be.
+pub fn register(
+  app: &AppHandle,
+  shortcut: &CaptureShortcut,
+  accelerator: &str,
+) -> Result<(), AppError> {
+  let mut current = sync::lock(&shortcut.0);
+  if current.as_deref() == Some(accelerator) {
+    return Ok(());
+  }
+
+  let mut manager = app.global_shortcut_manager();
+  let handle = app.clone();
+  manager
+    .register(accelerator, move || capture_into_editor(handle.clone()))
+    .map_err(|err| {
+      AppError::InvalidArgument(format!(
+        "could not register the shortcut {}: {}",
+        accelerator, err
+      ))
+    })?;
+  if let Some(previous) = current.replace(accelerator.to_string()) {
+    if let Err(err) = manager.unregister(&previous) {
+      tracing::warn!("failed to unregister the shortcut {}: {}", previous, err);
+    }
+  }
+  tracing::info!("capture shortcut set to {}", accelerator);
+  Ok(())
+}
+
+/// Captures the primary monitor with the main window out of the way, then
+/// brings the window back with the screenshot.
+fn capture_into_editor(app: AppHandle) {
+  // Shortcut handlers run on the event loop, which hiding the window needs
+  // to get back to.
+  thread::spawn(move || {
+    let Some(window) = app.get_window("main") else {
+      return;
+    };
+    if window.is_visible().unwrap_or(false) && window.hide().is_ok() {
+      thread::sleep(HIDE_DELAY);
+    }
+    let captured = capture_monitor(None);
+
+    let _ = window.unminimize();
+    let _ = window.show();
+    let _ = window.set_focus();
+    match captured {
+      Ok(image) => {
+        let _ = window.emit("screenshot-captured", ImageData::from_rgba(&image));
+      }
+      Err(err) => {
+        tracing::warn!("screenshot failed: {}", err);
+        let _ = window.emit("screenshot-failed", err);
+      }
+    }
+  });
+}
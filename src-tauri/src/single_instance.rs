@@ -0,0 +1,129 @@
+//! Keeping to one running instance.
+//!
+//! The first instance listens on a local socket (a named pipe on Windows).
+//! A later launch connects, hands over its arguments and working directory,
+//! and exits, so "Open With" on several files, which the OS does with one
+//! process per file, ends up in a single window.
+
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::thread;
+
+use interprocess::local_socket::{
+  prelude::*, GenericFilePath, GenericNamespaced, Listener, ListenerOptions, Name, Stream,
+};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::open_files::{self, OpenFiles};
+
+/// What a second launch passes to the running instance, as one line of
+/// JSON.
+#[derive(Debug, Serialize, Deserialize)]
+struct Launch {
+  cwd: PathBuf,
+  args: Vec<String>,
+}
+
+pub enum Instance {
+  /// This is the first instance, and should `listen` for later ones.
+  /// `None` if the socket couldn't be created, in which case every launch
+  /// runs on its own.
+  Primary(Option<Listener>),
+  /// Another instance is running and has been given this one's arguments.
+  Secondary,
+}
+
+/// Forwards this launch to the running instance, or becomes it.
+pub fn claim(identifier: &str) -> Instance {
+  let launch = Launch {
+    cwd: env::current_dir().unwrap_or_default(),
+    args: env::args().skip(1).collect(),
+  };
+  if forward(identifier, &launch).is_ok() {
+    return Instance::Secondary;
+  }
+
+  let listener = socket_name(identifier).and_then(|name| {
+    // Only socket files can be overwritten, and only a crash leaves one
+    // behind: nothing answered on it just now.
+    ListenerOptions::new()
+      .name(name)
+      .try_overwrite(true)
+      .create_sync()
+  });
+  match listener {
+    Ok(listener) => Instance::Primary(Some(listener)),
+    // Another launch started listening since we tried to connect.
+    Err(err) if err.kind() == io::ErrorKind::AddrInUse && forward(identifier, &launch).is_ok() => {
+      Instance::Secondary
+    }
+    Err(err) => {
+      tracing::warn!("running without single-instance handling: {}", err);
+      Instance::Primary(None)
+    }
+  }
+}
+
+/// Handles later launches for as long as the app runs: their files are
+/// opened and the main window is brought to the front.
+///
+/// Each connection is read on its own thread, so one that never sends its
+/// line can't hold up the launches after it. Named pipes have no read
+/// timeout to guard against that instead.
+pub fn listen(app: AppHandle, listener: Listener) {
+  thread::spawn(move || {
+    for conn in listener.incoming() {
+      match conn {
+        Ok(conn) => {
+          let app = app.clone();
+          thread::spawn(move || match read_launch(conn) {
+            Ok(launch) => receive(&app, launch),
+            Err(err) => tracing::warn!("failed to read a forwarded launch: {}", err),
+          });
+        }
+        Err(err) => tracing::warn!("failed to accept a forwarded launch: {}", err),
+      }
+    }
+  });
+}
+
+fn read_launch(conn: Stream) -> io::Result<Launch> {
+  let mut line = String::new();
+  BufReader::new(conn).read_line(&mut line)?;
+  serde_json::from_str(&line).map_err(io::Error::from)
+}
+
+fn receive(app: &AppHandle, launch: Launch) {
+  if let Some(window) = app.get_window("main") {
+    let _ = window.unminimize();
+    let _ = window.show();
+    let _ = window.set_focus();
+  }
+  let paths = open_files::paths_from_args(&launch.cwd, launch.args);
+  app.state::<OpenFiles>().open(app, paths);
+}
+
+fn forward(identifier: &str, launch: &Launch) -> io::Result<()> {
+  let mut stream = Stream::connect(socket_name(identifier)?)?;
+  let mut message = serde_json::to_vec(launch)?;
+  message.push(b'\n');
+  stream.write_all(&message)?;
+  stream.flush()
+}
+
+/// The socket's name, which includes the user so that people sharing a
+/// machine each get their own instance.
+fn socket_name(identifier: &str) -> io::Result<Name<'static>> {
+  let user = env::var("USERNAME")
+    .or_else(|_| env::var("USER"))
+    .unwrap_or_default();
+  let name = format!("{}-{}.sock", identifier, user);
+  if GenericNamespaced::is_supported() {
+    name.to_ns_name::<GenericNamespaced>()
+  } else {
+    // macOS has no socket namespace, so this is a file.
+    env::temp_dir().join(name).to_fs_name::<GenericFilePath>()
+  }
+}
@@ -0,0 +1,11 @@
+//! Helpers for state shared between commands.
+
+use std::sync::{Mutex, MutexGuard};
+
+/// Locks `mutex`, carrying on past a panic in another command: every
+/// piece of managed state stays consistent between its own updates.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+  mutex
+    .lock()
+    .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
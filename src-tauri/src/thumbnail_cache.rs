@@ -0,0 +1,121 @@
+//! On-disk cache of generated thumbnails.
+//!
+//! Entries are PNGs named after a hash of the source's absolute path and
+//! requested size, followed by a hash of its modification time and length.
+//! When the source changes, the lookup misses and `put` replaces the stale
+//! entry.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use image::{ImageError, ImageFormat, RgbaImage};
+
+use crate::error::AppError;
+
+/// Thumbnail cache rooted at a directory, held in managed state.
+#[derive(Debug)]
+pub struct ThumbnailCache {
+  dir: PathBuf,
+}
+
+/// Identifies one cached thumbnail.
+#[derive(Debug, Clone)]
+pub struct CacheKey {
+  /// Shared by every version of this source at this size.
+  prefix: String,
+  stamp: u64,
+}
+
+impl CacheKey {
+  fn file_name(&self) -> String {
+    format!("{}{:016x}.png", self.prefix, self.stamp)
+  }
+}
+
+impl ThumbnailCache {
+  pub fn new(dir: PathBuf) -> Self {
+    Self { dir }
+  }
+
+  /// Builds the key for a thumbnail of `source`, or `None` if the file
+  /// can't be inspected.
+  pub fn key(&self, source: &Path, max_edge: u32) -> Option<CacheKey> {
+    let absolute = fs::canonicalize(source).ok()?;
+    let metadata = fs::metadata(&absolute).ok()?;
+    let modified = metadata
+      .modified()
+      .ok()?
+      .duration_since(UNIX_EPOCH)
+      .ok()?
+      .as_nanos();
+
+    let path_hash = fnv1a(absolute.to_string_lossy().as_bytes());
+    let mut stamp = modified.to_le_bytes().to_vec();
+    stamp.extend_from_slice(&metadata.len().to_le_bytes());
+
+    Some(CacheKey {
+      prefix: format!("{:016x}-{}-", path_hash, max_edge),
+      stamp: fnv1a(&stamp),
+    })
+  }
+
+  pub fn get(&self, key: &CacheKey) -> Option<RgbaImage> {
+    let bytes = fs::read(self.dir.join(key.file_name())).ok()?;
+    image::load_from_memory_with_format(&bytes, ImageFormat::Png)
+      .ok()
+      .map(|image| image.to_rgba8())
+  }
+
+  /// Stores a thumbnail, removing entries for older versions of the same
+  /// source at the same size.
+  pub fn put(&self, key: &CacheKey, thumbnail: &RgbaImage) -> Result<(), AppError> {
+    fs::create_dir_all(&self.dir)
+      .map_err(|err| AppError::Io(format!("failed to create {}: {}", self.dir.display(), err)))?;
+
+    if let Ok(entries) = fs::read_dir(&self.dir) {
+      for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with(&key.prefix) {
+          let _ = fs::remove_file(entry.path());
+        }
+      }
+    }
+
+    let path = self.dir.join(key.file_name());
+    thumbnail
+      .save_with_format(&path, ImageFormat::Png)
+      .map_err(|err| match err {
+        ImageError::IoError(err) => AppError::write(&path, &err),
+        err => AppError::EncodeFailed(format!("failed to encode {}: {}", path.display(), err)),
+      })
+  }
+
+  /// Deletes every cached thumbnail, returning the number of bytes freed.
+  pub fn clear(&self) -> io::Result<u64> {
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(entries) => entries,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+      Err(err) => return Err(err),
+    };
+
+    let mut freed = 0;
+    for entry in entries {
+      let entry = entry?;
+      let metadata = entry.metadata()?;
+      if metadata.is_file() {
+        fs::remove_file(entry.path())?;
+        freed += metadata.len();
+      }
+    }
+    Ok(freed)
+  }
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` it is stable across Rust
+/// releases, so cache entries survive an app update.
+fn fnv1a(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+    (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+  })
+}
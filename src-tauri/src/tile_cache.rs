@@ -0,0 +1,79 @@
+//! Decoded pyramid levels that `load_tile` cuts tiles from.
+//!
+//! Panning around a big image asks for many tiles of the same level in a
+//! row, so each level is decoded once and kept until the memory limit pushes
+//! it out, least recently used first.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use image::RgbaImage;
+
+/// Most decoded bytes kept at once. The newest level is always kept, even
+/// if it alone is bigger.
+pub const MAX_BYTES: usize = 512 * 1024 * 1024;
+
+/// Identifies one level of one version of a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelKey {
+  path: PathBuf,
+  modified: SystemTime,
+  len: u64,
+  level: u32,
+}
+
+impl LevelKey {
+  /// Builds the key for `level` of `source`, or `None` if the file can't
+  /// be inspected.
+  pub fn new(source: &Path, level: u32) -> Option<Self> {
+    let path = fs::canonicalize(source).ok()?;
+    let metadata = fs::metadata(&path).ok()?;
+    Some(Self {
+      modified: metadata.modified().ok()?,
+      len: metadata.len(),
+      path,
+      level,
+    })
+  }
+}
+
+/// Decoded levels, least recently used first.
+#[derive(Debug, Default)]
+pub struct TileCache {
+  entries: VecDeque<(LevelKey, Arc<RgbaImage>)>,
+  bytes: usize,
+}
+
+impl TileCache {
+  /// The level stored under `key`, marking it recently used.
+  pub fn get(&mut self, key: &LevelKey) -> Option<Arc<RgbaImage>> {
+    let index = self.entries.iter().position(|(stored, _)| stored == key)?;
+    let entry = self.entries.remove(index)?;
+    let image = Arc::clone(&entry.1);
+    self.entries.push_back(entry);
+    Some(image)
+  }
+
+  /// Stores a level, dropping older versions of it and evicting the least
+  /// recently used levels past the limit.
+  pub fn insert(&mut self, key: LevelKey, image: Arc<RgbaImage>) {
+    self.entries.retain(|(stored, image)| {
+      let stale = stored.path == key.path && stored.level == key.level;
+      if stale {
+        self.bytes -= image.len();
+      }
+      !stale
+    });
+    self.bytes += image.len();
+    self.entries.push_back((key, image));
+
+    while self.entries.len() > 1 && self.bytes > MAX_BYTES {
+      if let Some((_, evicted)) = self.entries.pop_front() {
+        self.bytes -= evicted.len();
+      }
+    }
+  }
+}
@@ -0,0 +1,94 @@
+//! The system tray icon and its quick actions.
+
+use std::time::Duration;
+
+use tauri::{
+  AppHandle, CustomMenuItem, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu,
+  SystemTrayMenuItem,
+};
+
+use crate::commands::BatchCancel;
+use crate::window_state;
+
+const TOOLTIP: &str = "Image Pro";
+
+/// How long Quit waits for in-flight batch files to finish writing.
+const QUIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn build() -> SystemTray {
+  let menu = SystemTrayMenu::new()
+    .add_item(CustomMenuItem::new("show", "Show Window"))
+    .add_item(CustomMenuItem::new("new-batch", "New Batch Job"))
+    .add_native_item(SystemTrayMenuItem::Separator)
+    .add_item(CustomMenuItem::new("quit", "Quit"));
+  SystemTray::new().with_tooltip(TOOLTIP).with_menu(menu)
+}
+
+pub fn handle_event(app: &AppHandle, event: SystemTrayEvent) {
+  match event {
+    SystemTrayEvent::LeftClick { .. } => toggle_main_window(app),
+    SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
+      "show" => show_main_window(app),
+      "new-batch" => {
+        show_main_window(app);
+        let _ = app.emit_all("tray://new-batch", ());
+      }
+      "quit" => quit(app),
+      _ => {}
+    },
+    _ => {}
+  }
+}
+
+/// Shows batch progress in the tray tooltip, or the plain app name for
+/// `None`.
+pub fn set_progress(app: &AppHandle, progress: Option<(usize, usize)>) {
+  let tooltip = match progress {
+    Some((processed, total)) if total > 0 => {
+      format!("{} - batch {}%", TOOLTIP, processed * 100 / total)
+    }
+    _ => TOOLTIP.to_string(),
+  };
+  let _ = app.tray_handle().set_tooltip(&tooltip);
+}
+
+fn show_main_window(app: &AppHandle) {
+  if let Some(window) = app.get_window("main") {
+    let _ = window.unminimize();
+    let _ = window.show();
+    let _ = window.set_focus();
+  }
+}
+
+fn toggle_main_window(app: &AppHandle) {
+  if let Some(window) = app.get_window("main") {
+    if window.is_visible().unwrap_or(false) {
+      let _ = window.hide();
+    } else {
+      show_main_window(app);
+    }
+  }
+}
+
+/// Exits the app, first stopping any batch so no file is left half written.
+///
+/// The wait happens on its own thread so the event loop keeps running
+/// meanwhile.
+pub(crate) fn quit(app: &AppHandle) {
+  let app = app.clone();
+  std::thread::spawn(move || {
+    if !app.state::<BatchCancel>().cancel_and_wait(QUIT_TIMEOUT) {
+      tracing::warn!(
+        "batch still running after {:?}, exiting anyway",
+        QUIT_TIMEOUT
+      );
+    }
+    // Exiting skips the close handlers, so save the window state here.
+    if let Some(window) = app.get_window("main") {
+      if let Err(err) = window_state::save(&window) {
+        tracing::warn!("failed to save window state: {}", err);
+      }
+    }
+    app.exit(0);
+  });
+}
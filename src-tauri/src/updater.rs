@@ -0,0 +1,163 @@
+//! Checking for and installing new releases.
+//!
+//! Tauri's built-in dialog is off: the frontend hears `update-available`
+//! and decides how to offer the update, then calls `install_update`.
+//!
+//! Updates are only built with the `updater` feature. Turning them on also
+//! means making `tauri.updater` in tauri.conf.json active with the release
+//! endpoint and the public half of the signing key, and listing "updater"
+//! in tauri's features, which tauri-build checks against that config. Until
+//! then the startup check is skipped and `install_update` says why.
+
+#[cfg(feature = "updater")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "updater")]
+use std::sync::Mutex;
+
+#[cfg(feature = "updater")]
+use serde::Serialize;
+#[cfg(feature = "updater")]
+use tauri::updater::{self, UpdateResponse};
+use tauri::{AppHandle, RunEvent};
+#[cfg(feature = "updater")]
+use tauri::{Manager, UpdaterEvent};
+
+use crate::error::AppError;
+#[cfg(feature = "updater")]
+use crate::sync;
+
+/// The update found at startup, until it is installed.
+#[derive(Default)]
+pub struct PendingUpdate {
+  #[cfg(feature = "updater")]
+  update: Mutex<Option<UpdateResponse<tauri::Wry>>>,
+  #[cfg(feature = "updater")]
+  downloaded: AtomicU64,
+}
+
+#[cfg(feature = "updater")]
+impl PendingUpdate {
+  /// Takes the update found at startup, resetting the download progress.
+  pub fn take(&self) -> Option<UpdateResponse<tauri::Wry>> {
+    self.downloaded.store(0, Ordering::Relaxed);
+    sync::lock(&self.update).take()
+  }
+}
+
+#[cfg(feature = "updater")]
+#[derive(Debug, Clone, Serialize)]
+struct UpdateAvailable {
+  version: String,
+  notes: Option<String>,
+}
+
+#[cfg(feature = "updater")]
+#[derive(Debug, Clone, Serialize)]
+struct DownloadProgress {
+  downloaded: u64,
+  total: Option<u64>,
+}
+
+/// Errors unless the updater is built in and active with a signing key and
+/// an endpoint, since Tauri can't find or verify a release otherwise.
+pub fn ensure_configured(app: &AppHandle) -> Result<(), AppError> {
+  let config = &app.config().tauri.updater;
+  let has_endpoint = config
+    .endpoints
+    .as_ref()
+    .is_some_and(|endpoints| !endpoints.is_empty());
+  if cfg!(feature = "updater") && config.active && !config.pubkey.is_empty() && has_endpoint {
+    Ok(())
+  } else {
+    Err(AppError::NotConfigured(
+      "the updater is not configured: build with the `updater` feature and make tauri.updater \
+       active with a pubkey and an endpoint"
+        .into(),
+    ))
+  }
+}
+
+/// Checks the release endpoint in the background and emits
+/// `update-available` if there is a newer release.
+///
+/// Being offline, or any other failure to check, is only logged: there is
+/// nothing the user can do about it, and the next launch checks again.
+pub fn check(app: AppHandle) {
+  if let Err(err) = ensure_configured(&app) {
+    tracing::info!("skipped the update check: {}", err);
+  } else {
+    #[cfg(feature = "updater")]
+    tauri::async_runtime::spawn(async move {
+      match app.updater().skip_events().check().await {
+        Ok(update) if update.is_update_available() => {
+          let event = UpdateAvailable {
+            version: update.latest_version().to_string(),
+            notes: update.body().cloned(),
+          };
+          tracing::info!("update {} is available", event.version);
+          *sync::lock(&app.state::<PendingUpdate>().update) = Some(update);
+          let _ = app.emit_all("update-available", event);
+        }
+        Ok(_) | Err(updater::Error::UpToDate) => tracing::info!("no update available"),
+        Err(err) => tracing::info!("skipped the update check: {}", err),
+      }
+    });
+  }
+}
+
+/// Downloads and installs the release announced by `update-available`, or
+/// checks the endpoint again first if there wasn't one.
+pub async fn install(app: &AppHandle, pending: &PendingUpdate) -> Result<(), AppError> {
+  ensure_configured(app)?;
+
+  #[cfg(feature = "updater")]
+  {
+    let update = match pending.take() {
+      Some(update) => update,
+      None => app
+        .updater()
+        .skip_events()
+        .check()
+        .await
+        .ok()
+        .filter(|update| update.is_update_available())
+        .ok_or_else(|| AppError::NotFound("no update is available".into()))?,
+    };
+
+    let version = update.latest_version().to_string();
+    update
+      .download_and_install()
+      .await
+      .map_err(|err| AppError::Io(format!("failed to install update {}: {}", version, err)))?;
+    tracing::info!("installed update {}", version);
+  }
+  #[cfg(not(feature = "updater"))]
+  let _ = pending;
+  Ok(())
+}
+
+/// Forwards download progress from the run loop to the frontend as
+/// `update-download-progress`, with the bytes downloaded so far.
+pub fn handle_event(app: &AppHandle, event: RunEvent) {
+  #[cfg(feature = "updater")]
+  if let RunEvent::Updater(UpdaterEvent::DownloadProgress {
+    chunk_length,
+    content_length,
+  }) = event
+  {
+    let downloaded = app
+      .state::<PendingUpdate>()
+      .downloaded
+      .fetch_add(chunk_length as u64, Ordering::Relaxed)
+      + chunk_length as u64;
+    let _ = app.emit_all(
+      "update-download-progress",
+      DownloadProgress {
+        downloaded,
+        total: content_length,
+      },
+    );
+  }
+  #[cfg(not(feature = "updater"))]
+  let _ = (app, event);
+}
@@ -0,0 +1,122 @@
+//! Remembering the main window's size and position between launches.
+
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, PhysicalPosition, Window};
+
+use crate::error::AppError;
+
+const STATE_FILE: &str = "window-state.json";
+
+pub const DEFAULT_WIDTH: f64 = 1200.0;
+pub const DEFAULT_HEIGHT: f64 = 800.0;
+
+/// How far into a monitor, in physical pixels, the window's top-left corner
+/// must be for the title bar to count as reachable.
+const GRAB_MARGIN: i32 = 32;
+
+/// Saved geometry, in logical pixels so it survives DPI changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowState {
+  pub width: f64,
+  pub height: f64,
+  pub x: f64,
+  pub y: f64,
+  pub maximized: bool,
+}
+
+fn state_path(app: &AppHandle) -> Option<PathBuf> {
+  Some(app.path_resolver().app_config_dir()?.join(STATE_FILE))
+}
+
+/// Reads the saved state, if there is one and it parses.
+pub fn load(app: &AppHandle) -> Option<WindowState> {
+  let bytes = fs::read(state_path(app)?).ok()?;
+  serde_json::from_slice(&bytes).ok()
+}
+
+/// Writes the window's current geometry to the app config dir.
+pub fn save(window: &Window) -> Result<(), AppError> {
+  let app = window.app_handle();
+  let path = state_path(&app)
+    .ok_or_else(|| AppError::Io("could not resolve the app config directory".into()))?;
+  let maximized = window.is_maximized().map_err(window_error)?;
+
+  let state = if maximized {
+    // A maximized window reports the maximized geometry; keep the last
+    // normal one so un-maximizing after a relaunch restores it.
+    let previous = load(&app).unwrap_or(WindowState {
+      width: DEFAULT_WIDTH,
+      height: DEFAULT_HEIGHT,
+      x: 0.0,
+      y: 0.0,
+      maximized: true,
+    });
+    WindowState {
+      maximized: true,
+      ..previous
+    }
+  } else {
+    let scale = window.scale_factor().map_err(window_error)?;
+    let size = window
+      .inner_size()
+      .map_err(window_error)?
+      .to_logical::<f64>(scale);
+    let position = window
+      .outer_position()
+      .map_err(window_error)?
+      .to_logical::<f64>(scale);
+    WindowState {
+      width: size.width,
+      height: size.height,
+      x: position.x,
+      y: position.y,
+      maximized: false,
+    }
+  };
+
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)
+      .map_err(|err| AppError::Io(format!("failed to create {}: {}", parent.display(), err)))?;
+  }
+  let json = serde_json::to_vec_pretty(&state)
+    .map_err(|err| AppError::Io(format!("failed to write {}: {}", path.display(), err)))?;
+  fs::write(&path, json).map_err(|err| AppError::write(&path, &err))
+}
+
+fn window_error(err: tauri::Error) -> AppError {
+  AppError::Io(format!("failed to read the window geometry: {}", err))
+}
+
+/// Moves the window onto the primary monitor if its title bar isn't on any
+/// connected monitor, e.g. because it was last closed on a screen that has
+/// since been unplugged.
+pub fn ensure_visible(window: &Window) -> tauri::Result<()> {
+  let position = window.outer_position()?;
+  let grab = PhysicalPosition::new(position.x + GRAB_MARGIN, position.y + GRAB_MARGIN);
+
+  let on_screen = window.available_monitors()?.iter().any(|monitor| {
+    let origin = monitor.position();
+    let size = monitor.size();
+    grab.x >= origin.x
+      && grab.y >= origin.y
+      && grab.x < origin.x + size.width as i32
+      && grab.y < origin.y + size.height as i32
+  });
+  if on_screen {
+    return Ok(());
+  }
+
+  match window.primary_monitor()? {
+    Some(monitor) => {
+      let size = window.outer_size()?;
+      let origin = monitor.position();
+      let x = origin.x + (monitor.size().width as i32 - size.width as i32).max(0) / 2;
+      let y = origin.y + (monitor.size().height as i32 - size.height as i32).max(0) / 2;
+      window.set_position(PhysicalPosition::new(x, y))
+    }
+    None => window.center(),
+  }
+}